@@ -0,0 +1,87 @@
+//! Ringing the bell: flashing the window, asking for the attention of the user and running a
+//! command.
+
+use std::time::{Duration, Instant};
+
+/// How long the visual bell takes to fade out.
+pub const FLASH_DURATION: Duration = Duration::from_millis(150);
+
+/// Put in front of the window title when the bell rang while the window was not focused.
+pub const TITLE_MARKER: &str = "🔔 ";
+
+#[derive(Debug, Default)]
+pub struct Bell {
+    /// When the bell last rang, used to limit how often it rings.
+    last_rung: Option<Instant>,
+    /// When the visual bell started, if it is still showing.
+    flash_started: Option<Instant>,
+    /// The title is marked until the window is focused again.
+    pub marked: bool,
+}
+
+impl Bell {
+    /// Ring the bell, unless it already rang within the minimum interval. Returns `true` if it
+    /// rang.
+    pub fn ring(&mut self, now: Instant, config: &crate::config::BellConfig) -> bool {
+        if let Some(last_rung) = self.last_rung {
+            if now.saturating_duration_since(last_rung) < config.min_interval {
+                return false;
+            }
+        }
+
+        self.last_rung = Some(now);
+        if config.visual {
+            self.flash_started = Some(now);
+        }
+
+        true
+    }
+
+    /// Opacity of the visual bell, from 1 when it starts to 0, or `None` once it has faded out.
+    pub fn flash(&mut self, now: Instant) -> Option<f32> {
+        let elapsed = now.saturating_duration_since(self.flash_started?);
+        if elapsed >= FLASH_DURATION {
+            self.flash_started = None;
+            return None;
+        }
+
+        Some(1.0 - elapsed.as_secs_f32() / FLASH_DURATION.as_secs_f32())
+    }
+}
+
+/// Start the bell command without waiting for it to finish. The first element is the program,
+/// the rest its arguments.
+pub fn run_command(command: &[String]) {
+    let (program, arguments) = match command.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let child = std::process::Command::new(program)
+        .args(arguments)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .spawn();
+
+    match child {
+        // Wait on a separate thread so the process doesn't linger after it exits
+        Ok(mut child) => drop(std::thread::spawn(move || child.wait())),
+        Err(error) => warn!(%error, ?program, "failed to run the bell command"),
+    }
+}
+
+#[test]
+fn rate_limit_and_fade() {
+    let config = crate::config::BellConfig::default();
+    let mut bell = Bell::default();
+
+    let start = Instant::now();
+    assert!(bell.ring(start, &config));
+    assert!(!bell.ring(start + config.min_interval / 2, &config));
+    assert!(bell.ring(start + config.min_interval, &config));
+
+    let flash_start = start + config.min_interval;
+    assert_eq!(bell.flash(flash_start), Some(1.0));
+    assert!(bell.flash(flash_start + FLASH_DURATION / 2).unwrap() < 1.0);
+    assert_eq!(bell.flash(flash_start + FLASH_DURATION), None);
+}
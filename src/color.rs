@@ -1,9 +1,15 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Color {
     /// Use a color from the default palette
     Index(u8),
     /// Use a specific RGB color
     Rgb([u8; 3]),
+    /// The foreground color used when the application hasn't picked one. It looks the same as
+    /// palette entry 15, but is swapped with the background by reverse video.
+    DefaultForeground,
+    /// The background color used when the application hasn't picked one, which looks the same as
+    /// palette entry 0.
+    DefaultBackground,
 }
 
 pub type Palette = [[u8; 3]; 256];
@@ -13,6 +19,8 @@ impl Color {
         match self {
             Color::Index(index) => palette[index as usize],
             Color::Rgb(rgb) => rgb,
+            Color::DefaultForeground => palette[15],
+            Color::DefaultBackground => palette[0],
         }
     }
 
@@ -120,8 +128,18 @@ impl From<[u8; 3]> for Color {
     }
 }
 
-pub const DEFAULT_FOREGROUND: Color = Color::Index(15);
-pub const DEFAULT_BACKGROUND: Color = Color::Index(0);
+pub const DEFAULT_FOREGROUND: Color = Color::DefaultForeground;
+pub const DEFAULT_BACKGROUND: Color = Color::DefaultBackground;
+
+/// Swap the default foreground and background colors (`DECSCNM`). Colors picked by the
+/// application are unchanged, even if they look the same as the default ones.
+pub fn reverse_video(color: Color) -> Color {
+    match color {
+        Color::DefaultForeground => Color::DefaultBackground,
+        Color::DefaultBackground => Color::DefaultForeground,
+        color => color,
+    }
+}
 
 pub const DEFAULT_CURSOR: Color = DEFAULT_FOREGROUND;
 
@@ -193,3 +211,17 @@ pub const DEFAULT_PALETTE: Palette = {
 
     colors
 };
+
+#[test]
+fn reverse_only_default_colors() {
+    assert_eq!(reverse_video(DEFAULT_FOREGROUND), DEFAULT_BACKGROUND);
+    assert_eq!(reverse_video(DEFAULT_BACKGROUND), DEFAULT_FOREGROUND);
+
+    // Colors picked by the application stay, even if they look like the default ones
+    assert_eq!(reverse_video(Color::Index(15)), Color::Index(15));
+    assert_eq!(reverse_video(Color::Index(0)), Color::Index(0));
+    assert_eq!(
+        DEFAULT_FOREGROUND.into_rgb(&DEFAULT_PALETTE),
+        Color::Index(15).into_rgb(&DEFAULT_PALETTE)
+    );
+}
@@ -0,0 +1,72 @@
+//! Whether the user prefers a light or dark color scheme, which is reported to applications so
+//! their themes can follow it.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorScheme {
+    Dark,
+    Light,
+}
+
+impl ColorScheme {
+    /// Value used in color scheme reports (`CSI ? 997 ; Ps n`).
+    pub fn report_value(self) -> u8 {
+        match self {
+            ColorScheme::Dark => 1,
+            ColorScheme::Light => 2,
+        }
+    }
+
+    pub fn toggled(self) -> ColorScheme {
+        match self {
+            ColorScheme::Dark => ColorScheme::Light,
+            ColorScheme::Light => ColorScheme::Dark,
+        }
+    }
+}
+
+/// Where the preference of the operating system comes from.
+pub trait SystemPreference {
+    /// Returns `None` if the preference is not known.
+    fn color_scheme(&self) -> Option<ColorScheme>;
+}
+
+impl SystemPreference for Option<ColorScheme> {
+    fn color_scheme(&self) -> Option<ColorScheme> {
+        *self
+    }
+}
+
+/// The preference of the system, unless it has been overridden.
+pub struct Preference {
+    system: Box<dyn SystemPreference>,
+    overridden: Option<ColorScheme>,
+}
+
+impl Preference {
+    pub fn new(system: Box<dyn SystemPreference>, overridden: Option<ColorScheme>) -> Preference {
+        Preference { system, overridden }
+    }
+
+    pub fn current(&self) -> ColorScheme {
+        self.overridden
+            .or_else(|| self.system.color_scheme())
+            .unwrap_or(ColorScheme::Dark)
+    }
+
+    /// Override the preference with the opposite of the current color scheme.
+    pub fn toggle(&mut self) {
+        self.overridden = Some(self.current().toggled());
+    }
+}
+
+#[test]
+fn override_system_preference() {
+    let mut preference = Preference::new(Box::new(Some(ColorScheme::Light)), None);
+    assert_eq!(preference.current(), ColorScheme::Light);
+
+    preference.toggle();
+    assert_eq!(preference.current(), ColorScheme::Dark);
+
+    let preference = Preference::new(Box::new(None), None);
+    assert_eq!(preference.current(), ColorScheme::Dark);
+}
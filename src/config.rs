@@ -1,5 +1,161 @@
-
+#[derive(Debug, Clone)]
 pub struct Config {
+    /// Whether applications may move, resize or iconify the window (`CSI t`).
+    pub window_operations: WindowOperationPolicy,
+
+    /// Lines scrolled off the top of the main screen.
+    pub scrollback: ScrollbackConfig,
+
+    /// Characters with an ambiguous East Asian Width, such as Greek letters and box drawing
+    /// characters, take up two cells, as in CJK fonts.
+    pub wide_ambiguous_characters: bool,
+
+    /// Number of bytes images may use before the oldest ones are evicted.
+    pub image_memory_limit: usize,
+
+    /// How text from the clipboard is cleaned up before it is sent to the shell.
+    pub paste: PasteConfig,
+
+    /// Color scheme reported to applications instead of the preference of the system.
+    pub color_scheme: Option<crate::color_scheme::ColorScheme>,
+
+    /// What happens when an application rings the bell.
+    pub bell: BellConfig,
+
+    /// Title of the window, see `crate::title` for the fields it can use.
+    pub title_template: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            window_operations: WindowOperationPolicy::Deny,
+            scrollback: ScrollbackConfig::default(),
+            wide_ambiguous_characters: false,
+            image_memory_limit: crate::image::DEFAULT_MEMORY_LIMIT,
+            paste: PasteConfig::default(),
+            color_scheme: None,
+            bell: BellConfig::default(),
+            title_template: String::from("{bell}{title}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScrollbackConfig {
+    /// Maximum number of lines kept. The oldest lines are dropped first.
+    pub lines: usize,
+    /// Keep what was on the screen when an application clears it (`CSI 2 J`), as if it had
+    /// scrolled off.
+    pub save_cleared_screen: bool,
+    /// Go back to the bottom when the shell prints something.
+    pub scroll_to_bottom_on_output: bool,
+    /// Go back to the bottom when a key is pressed, except for shortcuts.
+    pub scroll_to_bottom_on_key_press: bool,
+}
+
+impl Default for ScrollbackConfig {
+    fn default() -> Self {
+        ScrollbackConfig {
+            lines: 10_000,
+            save_cleared_screen: true,
+            scroll_to_bottom_on_output: false,
+            scroll_to_bottom_on_key_press: true,
+        }
+    }
+}
 
+#[derive(Debug, Clone)]
+pub struct PasteConfig {
+    /// What happens to control characters other than tabs and newlines.
+    pub control_characters: ControlCharacters,
+    /// Send every newline (`CR LF`, `LF` or `CR`) as a carriage return, like the Return key.
+    pub normalize_newlines: bool,
+    /// Remove a single newline from the end, so the last line doesn't run straight away.
+    pub trim_trailing_newline: bool,
+    /// Ask before pasting more than one line while bracketed paste is off.
+    pub confirm_multiline: bool,
 }
 
+impl Default for PasteConfig {
+    fn default() -> Self {
+        PasteConfig {
+            control_characters: ControlCharacters::Strip,
+            normalize_newlines: true,
+            trim_trailing_newline: false,
+            confirm_multiline: true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ControlCharacters {
+    /// Remove them.
+    Strip,
+    /// Replace them with caret notation, such as `^[` for ESC.
+    Escape,
+}
+
+#[derive(Debug, Clone)]
+pub struct BellConfig {
+    /// Briefly flash the window.
+    pub visual: bool,
+    /// Ask for the attention of the user if the window is not focused.
+    pub urgency_hint: bool,
+    /// Program to run, followed by its arguments. Nothing is run if empty.
+    pub command: Vec<String>,
+    /// The bell is ignored if it rings again sooner than this.
+    pub min_interval: std::time::Duration,
+}
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        BellConfig {
+            visual: true,
+            urgency_hint: true,
+            command: Vec::new(),
+            min_interval: std::time::Duration::from_millis(200),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowOperationPolicy {
+    /// Ignore all requests to manipulate the window.
+    Deny,
+    /// Only allow requests that change the size of the window.
+    AllowResize,
+    /// Allow every request.
+    Allow,
+}
+
+impl WindowOperationPolicy {
+    pub fn allows(self, operation: crate::tty::control_code::WindowOperation) -> bool {
+        use crate::tty::control_code::WindowOperation;
+
+        match self {
+            WindowOperationPolicy::Deny => false,
+            WindowOperationPolicy::AllowResize => matches!(
+                operation,
+                WindowOperation::ResizePixels { .. }
+                    | WindowOperation::ResizeCells { .. }
+                    | WindowOperation::Maximize
+                    | WindowOperation::Unmaximize
+            ),
+            WindowOperationPolicy::Allow => true,
+        }
+    }
+}
+
+#[test]
+fn deny_window_operations_by_default() {
+    use crate::tty::control_code::WindowOperation;
+
+    // `DECCOLM` asks for the window to be resized, which is ignored unless allowed
+    let column_mode = WindowOperation::ResizeCells {
+        rows: None,
+        cols: Some(crate::screen::WIDE_COLUMNS),
+    };
+    assert!(!Config::default().window_operations.allows(column_mode));
+    assert!(WindowOperationPolicy::AllowResize.allows(column_mode));
+}
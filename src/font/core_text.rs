@@ -118,70 +118,118 @@ impl Font {
             &[glyph],
         );
 
-        let raster_left = bounds.origin.x.floor() as i32;
-        let raster_width =
-            (bounds.size.width + bounds.origin.x - raster_left as f64).ceil() as usize;
+        Some(rasterize_with(bounds, |context, origin| {
+            font.draw_glyphs(&[glyph], &[origin], context.clone())
+        }))
+    }
 
-        let raster_descent = (-bounds.origin.y).ceil() as i32;
-        let raster_ascent = (bounds.size.height + bounds.origin.y).ceil() as i32;
-        let raster_height = 1 + (raster_ascent + raster_descent) as usize;
+    /// Rasterize a grapheme cluster of several characters, which CoreText shapes into one or more
+    /// glyphs.
+    pub fn rasterize_cluster(&self, text: &str) -> Option<super::RasterizedGlyph> {
+        use core_foundation::attributed_string::CFMutableAttributedString;
+        use core_foundation::base::{CFRange, TCFType};
+        use core_foundation::string::CFString;
 
-        let metrics = super::GlyphMetrics {
-            ascent: raster_ascent,
-            bearing: raster_left,
-        };
+        let mut string = CFMutableAttributedString::new();
+        string.replace_str(&CFString::new(text), CFRange::init(0, 0));
+        let range = CFRange::init(0, string.char_len());
+        let font_attribute = unsafe { core_text::string_attributes::kCTFontAttributeName };
+        string.set_attribute(range, font_attribute, &self.cascade[0]);
 
-        let mut bitmap = super::Bitmap {
-            width: raster_width as u32,
-            height: raster_height as u32,
-            pixels: vec![[0u8; 4]; raster_width * raster_height],
-        };
+        let line =
+            core_text::line::CTLine::new_with_attributed_string(string.as_concrete_TypeRef());
+
+        // Measuring a line requires a context, even though nothing is drawn to it
+        let measure_context = create_context(None, 1, 1);
+        let bounds = line.get_image_bounds(&measure_context);
 
-        if raster_width > 0 && raster_height > 0 {
-            let color_space = {
-                let name = unsafe { core_graphics::color_space::kCGColorSpaceSRGB };
-                core_graphics::color_space::CGColorSpace::create_with_name(name)
-                    .unwrap_or_else(core_graphics::color_space::CGColorSpace::create_device_rgb)
-            };
-
-            let draw_context = core_graphics::context::CGContext::create_bitmap_context(
-                Some(bitmap.pixels.as_mut_ptr() as *mut _),
-                raster_width,
-                raster_height,
-                8,
-                raster_width * 4,
-                &color_space,
-                core_graphics::base::kCGImageAlphaPremultipliedLast
-                    | core_graphics::base::kCGBitmapByteOrder32Big,
-            );
-
-            draw_context.set_allows_antialiasing(true);
-            draw_context.set_allows_font_smoothing(true);
-            draw_context.set_allows_font_subpixel_positioning(true);
-            draw_context.set_allows_font_subpixel_quantization(true);
-
-            draw_context.set_should_antialias(true);
-            draw_context.set_should_smooth_fonts(true);
-            draw_context.set_should_subpixel_position_fonts(true);
-            draw_context.set_should_subpixel_quantize_fonts(true);
-
-            draw_context.set_rgb_fill_color(1.0, 1.0, 1.0, 1.0);
-            font.draw_glyphs(
-                &[glyph],
-                &[core_graphics::geometry::CGPoint::new(
-                    -raster_left as f64,
-                    raster_descent as f64,
-                )],
-                draw_context.clone(),
-            );
-            draw_context.flush();
-            drop(draw_context);
+        if bounds.size.width <= 0.0 || bounds.size.height <= 0.0 {
+            return None;
         }
 
-        Some(super::RasterizedGlyph { bitmap, metrics })
+        Some(rasterize_with(bounds, |context, origin| {
+            context.set_text_position(origin.x, origin.y);
+            line.draw(context);
+        }))
     }
 }
 
+/// Rasterize something with the given bounds relative to its origin on the baseline.
+fn rasterize_with(
+    bounds: core_graphics::geometry::CGRect,
+    draw: impl FnOnce(&core_graphics::context::CGContext, core_graphics::geometry::CGPoint),
+) -> super::RasterizedGlyph {
+    let raster_left = bounds.origin.x.floor() as i32;
+    let raster_width = (bounds.size.width + bounds.origin.x - raster_left as f64).ceil() as usize;
+
+    let raster_descent = (-bounds.origin.y).ceil() as i32;
+    let raster_ascent = (bounds.size.height + bounds.origin.y).ceil() as i32;
+    let raster_height = 1 + (raster_ascent + raster_descent) as usize;
+
+    let metrics = super::GlyphMetrics {
+        ascent: raster_ascent,
+        bearing: raster_left,
+    };
+
+    let mut bitmap = super::Bitmap {
+        width: raster_width as u32,
+        height: raster_height as u32,
+        pixels: vec![[0u8; 4]; raster_width * raster_height],
+    };
+
+    if raster_width > 0 && raster_height > 0 {
+        let draw_context = create_context(
+            Some(bitmap.pixels.as_mut_ptr() as *mut _),
+            raster_width,
+            raster_height,
+        );
+
+        draw_context.set_allows_antialiasing(true);
+        draw_context.set_allows_font_smoothing(true);
+        draw_context.set_allows_font_subpixel_positioning(true);
+        draw_context.set_allows_font_subpixel_quantization(true);
+
+        draw_context.set_should_antialias(true);
+        draw_context.set_should_smooth_fonts(true);
+        draw_context.set_should_subpixel_position_fonts(true);
+        draw_context.set_should_subpixel_quantize_fonts(true);
+
+        draw_context.set_rgb_fill_color(1.0, 1.0, 1.0, 1.0);
+        draw(
+            &draw_context,
+            core_graphics::geometry::CGPoint::new(-raster_left as f64, raster_descent as f64),
+        );
+        draw_context.flush();
+        drop(draw_context);
+    }
+
+    super::RasterizedGlyph { bitmap, metrics }
+}
+
+/// An sRGB context with premultiplied alpha.
+fn create_context(
+    pixels: Option<*mut std::ffi::c_void>,
+    width: usize,
+    height: usize,
+) -> core_graphics::context::CGContext {
+    let color_space = {
+        let name = unsafe { core_graphics::color_space::kCGColorSpaceSRGB };
+        core_graphics::color_space::CGColorSpace::create_with_name(name)
+            .unwrap_or_else(core_graphics::color_space::CGColorSpace::create_device_rgb)
+    };
+
+    core_graphics::context::CGContext::create_bitmap_context(
+        pixels,
+        width,
+        height,
+        8,
+        width * 4,
+        &color_space,
+        core_graphics::base::kCGImageAlphaPremultipliedLast
+            | core_graphics::base::kCGBitmapByteOrder32Big,
+    )
+}
+
 fn find_glyph(
     ch: char,
     cascade: &[core_text::font::CTFont],
@@ -1,7 +1,146 @@
 pub struct CharacterGrid {
     rows: u16,
     cols: u16,
+    /// Cells of every row, one row after the other. Rows are not stored in the order they are
+    /// shown, so that scrolling doesn't have to move their cells; use `row_cells` to find a row.
     cells: Vec<GridCell>,
+    /// Where each row is stored in `cells`, as a ring starting at `first_row`. Scrolling the whole
+    /// grid only moves `first_row`, and scrolling part of it swaps the entries of the rows.
+    storage: Vec<u16>,
+    first_row: u16,
+    /// Sizes of the rows, in the order they are stored in.
+    line_sizes: Vec<LineSize>,
+    /// Rows which continue on the next row because the text wrapped automatically, in the order
+    /// they are stored in.
+    wrapped: Vec<bool>,
+    /// Rows which changed since the damage was last taken.
+    damaged_rows: Vec<bool>,
+    damaged_all: bool,
+    clusters: Clusters,
+    attributes: AttributeTable,
+    history: History,
+}
+
+/// Rows of the grid which changed since the damage was last taken, so that renderers only have to
+/// draw those again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Damage {
+    /// Every row changed, such as after the grid was resized or scrolled as a whole.
+    All,
+    /// The rows which changed, from top to bottom.
+    Rows(Vec<u16>),
+}
+
+impl Damage {
+    pub fn contains(&self, row: u16) -> bool {
+        match self {
+            Damage::All => true,
+            Damage::Rows(rows) => rows.binary_search(&row).is_ok(),
+        }
+    }
+
+    /// The damage as it is seen when the view is scrolled `offset` lines up into the history,
+    /// which moves the rows of the grid down. Rows moved out of a view of `rows` rows are left
+    /// out.
+    pub fn in_view(self, offset: usize, rows: u16) -> Damage {
+        match self {
+            Damage::All => Damage::All,
+            Damage::Rows(damaged) => Damage::Rows(
+                damaged
+                    .into_iter()
+                    .map(|row| usize::from(row) + offset)
+                    .filter(|&row| row < usize::from(rows))
+                    .map(|row| row as u16)
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Maximum number of distinct grapheme clusters a grid can refer to at once. Cells with more are
+/// shown as their first character.
+const MAX_CLUSTERS: usize = 1 << 16;
+
+/// Number of stored grapheme clusters at which those no longer used by any cell are first dropped.
+const COMPACT_CLUSTERS_AT: usize = 1 << 12;
+
+/// Maximum number of characters in a grapheme cluster. Further combining marks are dropped, so
+/// that text stacked with thousands of them can't grow a cell without bounds.
+const MAX_CLUSTER_CHARS: usize = 32;
+
+/// Identifies a grapheme cluster of more than one character stored in a `CharacterGrid`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ClusterId(std::num::NonZeroU32);
+
+impl ClusterId {
+    fn from_index(index: usize) -> ClusterId {
+        ClusterId(std::num::NonZeroU32::new(index as u32 + 1).unwrap())
+    }
+
+    fn index(self) -> usize {
+        self.0.get() as usize - 1
+    }
+}
+
+/// Grapheme clusters used by the cells of a grid. Every distinct cluster is only stored once, and
+/// unused clusters are dropped once there are many of them, like the attributes.
+struct Clusters {
+    texts: Vec<std::rc::Rc<str>>,
+    ids: std::collections::HashMap<std::rc::Rc<str>, ClusterId>,
+    compact_at: usize,
+}
+
+impl Default for Clusters {
+    fn default() -> Self {
+        Clusters {
+            texts: Vec::new(),
+            ids: std::collections::HashMap::new(),
+            compact_at: COMPACT_CLUSTERS_AT,
+        }
+    }
+}
+
+/// Rows that scrolled off the top of the grid, oldest first.
+#[derive(Default)]
+struct History {
+    lines: std::collections::VecDeque<HistoryLine>,
+    /// Maximum number of lines. The oldest lines are dropped to make room for new ones.
+    limit: usize,
+    /// Number of lines ever pushed, including those which have been dropped or cleared since.
+    pushed: u64,
+}
+
+/// A row in the history. Keeps the width the grid had when it scrolled off.
+struct HistoryLine {
+    cells: Box<[GridCell]>,
+    size: LineSize,
+    wrapped: bool,
+}
+
+/// A row of the grid or the history. History lines may be narrower or wider than the grid.
+#[derive(Copy, Clone)]
+pub struct Row<'a> {
+    pub cells: &'a [GridCell],
+    pub size: LineSize,
+    /// The text continues on the next row, because it wrapped automatically.
+    pub wrapped: bool,
+}
+
+/// How the characters of a row are scaled (`DECSWL`, `DECDWL` and `DECDHL`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineSize {
+    Single,
+    DoubleWidth,
+    /// Double width, showing the top half of double height characters.
+    DoubleHeightTop,
+    /// Double width, showing the bottom half of double height characters.
+    DoubleHeightBottom,
+}
+
+impl LineSize {
+    pub fn is_double_width(self) -> bool {
+        !matches!(self, LineSize::Single)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
@@ -16,18 +155,133 @@ impl Position {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct GridCell {
-    pub character: char,
+/// How many columns the character in a cell takes up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CellWidth {
+    Single,
+    /// The character also covers the next cell, which is a `Spacer`.
+    Wide,
+    /// Covered by the wide character in the previous cell, or left empty at the end of a row
+    /// because the wide character after it did not fit.
+    Spacer,
+}
+
+/// A character with its width and attributes, packed into eight bytes so that rows stay small.
+/// Grapheme clusters and attributes are stored once in the `CharacterGrid`, and cells refer to
+/// them by id:
+///
+/// | Bits  | Field                                        |
+/// |-------|----------------------------------------------|
+/// | 0-20  | first character of the grapheme cluster      |
+/// | 21-22 | `CellWidth`                                  |
+/// | 23-39 | `ClusterId`, or zero for a single character  |
+/// | 40-63 | `AttributesId`                               |
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct GridCell(u64);
+
+const CHARACTER_BITS: u64 = 0x1F_FFFF;
+const WIDTH_SHIFT: u32 = 21;
+const WIDTH_BITS: u64 = 0b11 << WIDTH_SHIFT;
+const CLUSTER_SHIFT: u32 = 23;
+const CLUSTER_BITS: u64 = 0x1_FFFF << CLUSTER_SHIFT;
+const ATTRIBUTES_SHIFT: u32 = 40;
+const ATTRIBUTES_BITS: u64 = 0xFF_FFFF << ATTRIBUTES_SHIFT;
+
+impl GridCell {
+    pub const fn empty() -> Self {
+        GridCell(' ' as u64)
+    }
+
+    pub fn new(character: char, attributes: AttributesId) -> Self {
+        let mut cell = GridCell::empty();
+        cell.set_character(character);
+        cell.set_attributes(attributes);
+        cell
+    }
+
+    /// The first character of the grapheme cluster in the cell.
+    pub fn character(self) -> char {
+        // SAFETY: the bits are only ever set from a `char`
+        unsafe { char::from_u32_unchecked((self.0 & CHARACTER_BITS) as u32) }
+    }
+
+    pub fn set_character(&mut self, character: char) {
+        self.0 = (self.0 & !CHARACTER_BITS) | u64::from(character);
+    }
+
+    pub fn width(self) -> CellWidth {
+        match (self.0 & WIDTH_BITS) >> WIDTH_SHIFT {
+            1 => CellWidth::Wide,
+            2 => CellWidth::Spacer,
+            _ => CellWidth::Single,
+        }
+    }
+
+    pub fn set_width(&mut self, width: CellWidth) {
+        let bits = match width {
+            CellWidth::Single => 0,
+            CellWidth::Wide => 1,
+            CellWidth::Spacer => 2,
+        };
+        self.0 = (self.0 & !WIDTH_BITS) | (bits << WIDTH_SHIFT);
+    }
+
+    /// Set if the grapheme cluster has more than one character.
+    pub fn cluster(self) -> Option<ClusterId> {
+        let bits = ((self.0 & CLUSTER_BITS) >> CLUSTER_SHIFT) as u32;
+        std::num::NonZeroU32::new(bits).map(ClusterId)
+    }
+
+    pub fn set_cluster(&mut self, cluster: Option<ClusterId>) {
+        let bits = cluster.map_or(0, |id| u64::from(id.0.get()));
+        self.0 = (self.0 & !CLUSTER_BITS) | (bits << CLUSTER_SHIFT);
+    }
+
+    pub fn attributes(self) -> AttributesId {
+        AttributesId(((self.0 & ATTRIBUTES_BITS) >> ATTRIBUTES_SHIFT) as u32)
+    }
+
+    pub fn set_attributes(&mut self, attributes: AttributesId) {
+        self.0 = (self.0 & !ATTRIBUTES_BITS) | (u64::from(attributes.0) << ATTRIBUTES_SHIFT);
+    }
+
+    /// Remove the character and its cluster, keeping the attributes.
+    pub fn clear_text(&mut self) {
+        self.set_character(' ');
+        self.set_cluster(None);
+        self.set_width(CellWidth::Single);
+    }
+}
+
+impl Default for GridCell {
+    fn default() -> Self {
+        GridCell::empty()
+    }
+}
+
+impl std::fmt::Debug for GridCell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GridCell")
+            .field("character", &self.character())
+            .field("cluster", &self.cluster())
+            .field("width", &self.width())
+            .field("attributes", &self.attributes())
+            .finish()
+    }
+}
+
+/// Colors and styles of a cell. Most cells share a few combinations, so each one is stored once
+/// in the `CharacterGrid` and cells refer to it by `AttributesId`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Attributes {
     pub foreground: crate::color::Color,
     pub background: crate::color::Color,
     pub style: crate::tty::control_code::CharacterStyles,
 }
 
-impl GridCell {
-    pub const fn empty() -> Self {
-        GridCell {
-            character: ' ',
+impl Default for Attributes {
+    fn default() -> Self {
+        Attributes {
             foreground: crate::color::DEFAULT_FOREGROUND,
             background: crate::color::DEFAULT_BACKGROUND,
             style: crate::tty::control_code::CharacterStyles::empty(),
@@ -35,9 +289,43 @@ impl GridCell {
     }
 }
 
-impl Default for GridCell {
+/// Identifies a set of `Attributes` stored in a `CharacterGrid`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AttributesId(u32);
+
+impl AttributesId {
+    /// The default colors without any styles, which every grid has.
+    pub const DEFAULT: AttributesId = AttributesId(0);
+}
+
+/// Maximum number of distinct attributes a grid can refer to at once. Cells with more are shown
+/// with the default attributes.
+const MAX_ATTRIBUTES: usize = 1 << 24;
+
+/// Number of stored attributes at which those no longer used by any cell are first dropped.
+const COMPACT_ATTRIBUTES_AT: usize = 1 << 12;
+
+/// Attributes used by the cells of a grid. Unused attributes are dropped once there are many of
+/// them, such as after a program has drawn a gradient in true color.
+struct AttributeTable {
+    sets: Vec<Attributes>,
+    ids: std::collections::HashMap<Attributes, AttributesId>,
+    /// The last attributes that were looked up, which are usually used by the next cell as well.
+    recent: (Attributes, AttributesId),
+    compact_at: usize,
+}
+
+impl Default for AttributeTable {
     fn default() -> Self {
-        GridCell::empty()
+        let mut ids = std::collections::HashMap::new();
+        ids.insert(Attributes::default(), AttributesId::DEFAULT);
+
+        AttributeTable {
+            sets: vec![Attributes::default()],
+            ids,
+            recent: (Attributes::default(), AttributesId::DEFAULT),
+            compact_at: COMPACT_ATTRIBUTES_AT,
+        }
     }
 }
 
@@ -61,9 +349,42 @@ impl CharacterGrid {
             rows,
             cols,
             cells: vec![GridCell::default(); cols as usize * rows as usize],
+            storage: (0..rows).collect(),
+            first_row: 0,
+            line_sizes: vec![LineSize::Single; rows as usize],
+            wrapped: vec![false; rows as usize],
+            damaged_rows: vec![false; rows as usize],
+            damaged_all: true,
+            clusters: Clusters::default(),
+            attributes: AttributeTable::default(),
+            history: History::default(),
         }
     }
 
+    /// A grid showing one line of text per row. Text that doesn't fit is cut off. The first line
+    /// is bold.
+    pub fn from_lines(lines: &[String], size: [u16; 2]) -> CharacterGrid {
+        let [rows, cols] = size;
+        let mut grid = CharacterGrid::new(rows, cols);
+        let bold = grid.intern_attributes(Attributes {
+            style: crate::tty::control_code::CharacterStyles::BOLD,
+            ..Attributes::default()
+        });
+
+        for (row, line) in (0..rows).zip(lines) {
+            let attributes = if row == 0 {
+                bold
+            } else {
+                AttributesId::DEFAULT
+            };
+            for (col, character) in (0..cols).zip(line.chars()) {
+                grid[Position::new(row, col)] = GridCell::new(character, attributes);
+            }
+        }
+
+        grid
+    }
+
     pub fn size(&self) -> [u16; 2] {
         [self.rows, self.cols]
     }
@@ -84,6 +405,284 @@ impl CharacterGrid {
         self.rows - 1
     }
 
+    /// Change the size of the grid, keeping the cells in the top-left corner. Rows and columns
+    /// that no longer fit are cut off, and new ones are empty.
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        let mut cells = vec![GridCell::default(); cols as usize * rows as usize];
+        let mut line_sizes = vec![LineSize::Single; rows as usize];
+        let mut wrapped = vec![false; rows as usize];
+
+        let kept_cols = cols.min(self.cols) as usize;
+        for row in 0..rows.min(self.rows) {
+            let new_start = row as usize * cols as usize;
+            cells[new_start..new_start + kept_cols]
+                .copy_from_slice(&self.row_cells(row)[..kept_cols]);
+            line_sizes[row as usize] = self.line_size(row);
+            // Wrapped rows are no longer continued if the columns they wrapped at are cut off
+            wrapped[row as usize] = self.is_wrapped(row) && kept_cols == self.cols as usize;
+        }
+
+        self.rows = rows;
+        self.cols = cols;
+        self.cells = cells;
+        self.storage = (0..rows).collect();
+        self.first_row = 0;
+        self.line_sizes = line_sizes;
+        self.wrapped = wrapped;
+        self.damaged_rows = vec![false; rows as usize];
+        self.damaged_all = true;
+    }
+
+    /// Change the size of the grid and rewrap the lines on it and in the history, so that text
+    /// which wrapped automatically is wrapped at the new width. Rows move to and from the
+    /// history to keep the last line with text and `cursor` on the grid. Returns the new position
+    /// of the cursor.
+    pub fn reflow(&mut self, rows: u16, cols: u16, cursor: Position) -> Position {
+        let cursor = Position::new(cursor.row.min(self.max_row()), cursor.col);
+
+        // Rows below the cursor which are blank are dropped, so that they don't push lines off
+        // the top of the grid when it becomes smaller
+        let last_row = (0..self.rows)
+            .rev()
+            .find(|&row| !self.is_row_blank(row))
+            .unwrap_or(0)
+            .max(cursor.row);
+
+        let old_rows = self
+            .history
+            .lines
+            .iter()
+            .map(|line| (&*line.cells, line.size, line.wrapped))
+            .chain((0..=last_row).map(|row| {
+                (
+                    self.row_cells(row),
+                    self.line_size(row),
+                    self.is_wrapped(row),
+                )
+            }));
+
+        // Join the rows of each line, and find out where the cursor is in its line
+        let cursor_index = self.history.lines.len() + cursor.row as usize;
+        let mut lines: Vec<(Vec<GridCell>, LineSize)> = Vec::new();
+        let mut cursor_in_line = (0, 0);
+        let mut continued = false;
+
+        for (index, (cells, size, wrapped)) in old_rows.enumerate() {
+            if !continued {
+                lines.push((Vec::with_capacity(cells.len()), size));
+            }
+
+            let line_index = lines.len() - 1;
+            let (line, _) = &mut lines[line_index];
+            if index == cursor_index {
+                cursor_in_line = (line_index, line.len() + cursor.col as usize);
+            }
+
+            // Spacers left where a wide character did not fit are not part of the line
+            let cells = match cells.split_last() {
+                Some((last, rest)) if wrapped && is_leading_spacer(rest, last) => rest,
+                _ => cells,
+            };
+            line.extend_from_slice(cells);
+
+            // Rows of other sizes are never rewrapped
+            continued = wrapped && size == LineSize::Single;
+        }
+
+        let mut new_rows = Vec::new();
+        let mut new_cursor = (0, 0);
+
+        for (index, (cells, size)) in lines.into_iter().enumerate() {
+            let mut length = cells
+                .iter()
+                .rposition(|cell| !self.is_blank(cell))
+                .map_or(0, |last| last + 1);
+
+            let cursor_offset = if index == cursor_in_line.0 {
+                length = length.max(cursor_in_line.1);
+                Some(cursor_in_line.1)
+            } else {
+                None
+            };
+
+            let width = if size == LineSize::Single {
+                cols as usize
+            } else {
+                // Double width rows are cut off instead
+                length = length.min(cols as usize);
+                usize::MAX
+            };
+
+            let mut start: usize = 0;
+            loop {
+                let mut end = length.min(start.saturating_add(width));
+                // Wide characters are moved to the next row instead of being split
+                if end < length && end > start + 1 && cells[end - 1].width() == CellWidth::Wide {
+                    end -= 1;
+                }
+
+                if let Some(offset) = cursor_offset {
+                    let last = end >= length;
+                    if (start..end).contains(&offset) || (last && offset >= start) {
+                        new_cursor = (new_rows.len(), (offset - start).min(cols as usize));
+                    }
+                }
+
+                let mut row = vec![GridCell::default(); cols as usize];
+                let copied = (end - start).min(cols as usize);
+                row[..copied].copy_from_slice(&cells[start..start + copied]);
+                if end < length && copied < row.len() {
+                    row[copied].set_width(CellWidth::Spacer);
+                }
+
+                new_rows.push(HistoryLine {
+                    cells: row.into_boxed_slice(),
+                    size,
+                    wrapped: end < length,
+                });
+
+                start = end;
+                if start >= length {
+                    break;
+                }
+            }
+        }
+
+        // Keep as many rows as fit on the grid, as long as the cursor stays on it
+        let grid_start = new_rows
+            .len()
+            .saturating_sub(rows as usize)
+            .min(new_cursor.0);
+
+        let mut grid_rows = new_rows.split_off(grid_start);
+        grid_rows.truncate(rows as usize);
+
+        let excess = new_rows.len().saturating_sub(self.history.limit);
+        self.history.lines = new_rows.into_iter().skip(excess).collect();
+
+        self.rows = rows;
+        self.cols = cols;
+        self.cells = vec![GridCell::default(); cols as usize * rows as usize];
+        self.storage = (0..rows).collect();
+        self.first_row = 0;
+        self.line_sizes = vec![LineSize::Single; rows as usize];
+        self.wrapped = vec![false; rows as usize];
+        self.damaged_rows = vec![false; rows as usize];
+        self.damaged_all = true;
+
+        for (row, line) in grid_rows.into_iter().enumerate() {
+            let start = row * cols as usize;
+            self.cells[start..start + cols as usize].copy_from_slice(&line.cells);
+            self.line_sizes[row] = line.size;
+            self.wrapped[row] = line.wrapped;
+        }
+
+        Position::new((new_cursor.0 - grid_start) as u16, new_cursor.1 as u16)
+    }
+
+    pub fn line_size(&self, row: u16) -> LineSize {
+        self.line_sizes[self.storage_row(row)]
+    }
+
+    pub fn set_line_size(&mut self, row: u16, size: LineSize) {
+        self.damage_row(row);
+        let row = self.storage_row(row);
+        self.line_sizes[row] = size;
+    }
+
+    fn is_wrapped(&self, row: u16) -> bool {
+        self.wrapped[self.storage_row(row)]
+    }
+
+    /// Mark whether the text on the row continues on the next row, because it wrapped
+    /// automatically.
+    pub fn set_wrapped(&mut self, row: u16, wrapped: bool) {
+        let row = self.storage_row(row);
+        self.wrapped[row] = wrapped;
+    }
+
+    /// Where a row is stored in `cells`, counted in rows.
+    fn storage_row(&self, row: u16) -> usize {
+        let ring_index = self.first_row as usize + row as usize;
+        let ring_index = if ring_index >= self.rows as usize {
+            ring_index - self.rows as usize
+        } else {
+            ring_index
+        };
+        self.storage[ring_index] as usize
+    }
+
+    fn row_cells(&self, row: u16) -> &[GridCell] {
+        let start = self.storage_row(row) * self.cols as usize;
+        &self.cells[start..start + self.cols as usize]
+    }
+
+    fn row_cells_mut(&mut self, row: u16) -> &mut [GridCell] {
+        let start = self.storage_row(row) * self.cols as usize;
+        &mut self.cells[start..start + self.cols as usize]
+    }
+
+    /// Number of columns that fit on the given row, taking its line size into account.
+    pub fn cols_in_row(&self, row: u16) -> u16 {
+        self.cols_with_size(self.line_size(row))
+    }
+
+    /// Number of columns that fit on a row of the given size.
+    pub fn cols_with_size(&self, size: LineSize) -> u16 {
+        if size.is_double_width() {
+            (self.cols / 2).max(1)
+        } else {
+            self.cols
+        }
+    }
+
+    /// A row as shown when the view is scrolled `offset` lines up into the history. The top rows
+    /// come from the end of the history and the rest from the top of the grid.
+    pub fn view_row(&self, offset: usize, row: u16) -> Row<'_> {
+        let offset = offset.min(self.history.lines.len());
+
+        if usize::from(row) < offset {
+            let line = &self.history.lines[self.history.lines.len() - offset + usize::from(row)];
+            return Row {
+                cells: &line.cells,
+                size: line.size,
+                wrapped: line.wrapped,
+            };
+        }
+
+        let row = row - offset as u16;
+        Row {
+            cells: self.row_cells(row),
+            size: self.line_size(row),
+            wrapped: self.is_wrapped(row),
+        }
+    }
+
+    /// Text of the rows shown when the view is scrolled `offset` lines up into the history.
+    /// Rows which wrapped automatically are joined, and other rows end in a newline without
+    /// trailing spaces.
+    pub fn view_text(&self, offset: usize) -> String {
+        let mut text = String::new();
+
+        for row in 0..self.rows {
+            let row = self.view_row(offset, row);
+            for cell in row.cells {
+                if cell.width() != CellWidth::Spacer {
+                    self.push_cell_text(cell, &mut text);
+                }
+            }
+
+            if !row.wrapped {
+                text.truncate(text.trim_end_matches(' ').len());
+                text.push('\n');
+            }
+        }
+
+        text
+    }
+
+    /// Fill the region with a cell. Rows that are cleared completely are reset to single size, and
+    /// rows cleared up to their end no longer continue on the next row.
     pub fn fill_region(
         &mut self,
         row_range: impl std::ops::RangeBounds<u16>,
@@ -93,32 +692,366 @@ impl CharacterGrid {
         let rows = into_exclusive_range(row_range, self.rows);
         let columns = into_exclusive_range(col_range, self.cols);
 
-        // skip iterating over every row every column is cleared
-        if columns.start == 0 && columns.end == self.max_col() {
-            let row_start = rows.start as usize * self.cols as usize;
-            let row_end = rows.end as usize * self.cols as usize;
-            self.cells[row_start..row_end].fill(cell);
-        } else {
-            for row in rows {
-                let row_index = row as usize * self.cols as usize;
+        for row in rows {
+            self.damage_row(row);
+            self.row_cells_mut(row)[columns.start as usize..columns.end as usize].fill(cell);
+
+            if columns.start == 0 && columns.end == self.cols {
+                self.set_line_size(row, LineSize::Single);
+            }
+            if columns.end == self.cols {
+                self.set_wrapped(row, false);
+            }
+        }
+    }
+
+    /// Replace the halves of wide characters that are left over when the columns `cols` of `row`
+    /// are about to be overwritten with spaces.
+    pub fn clear_split_wide_characters(&mut self, row: u16, cols: std::ops::Range<u16>) {
+        if cols.is_empty() {
+            return;
+        }
+
+        let first = Position::new(row, cols.start);
+        if self[first].width() == CellWidth::Spacer && cols.start > 0 {
+            let previous = Position::new(row, cols.start - 1);
+            if self[previous].width() == CellWidth::Wide {
+                self[previous].clear_text();
+            }
+        }
+
+        let last = Position::new(row, cols.end - 1);
+        if self[last].width() == CellWidth::Wide && cols.end < self.cols {
+            self[Position::new(row, cols.end)].clear_text();
+        }
+    }
+
+    /// Store a grapheme cluster so cells can refer to it. Characters past `MAX_CLUSTER_CHARS` are
+    /// dropped. Returns `None` if the cluster is a single character, or too many clusters are in
+    /// use. Like attributes, unused clusters may be dropped first, which changes the ids of the
+    /// others.
+    pub fn intern_cluster(&mut self, text: &str) -> Option<ClusterId> {
+        let mut chars = text.char_indices();
+        if chars.next().is_none() || chars.next().is_none() {
+            return None;
+        }
+
+        let text = match text.char_indices().nth(MAX_CLUSTER_CHARS) {
+            Some((end, _)) => &text[..end],
+            None => text,
+        };
+
+        if let Some(&id) = self.clusters.ids.get(text) {
+            return Some(id);
+        }
+
+        if self.clusters.texts.len() >= self.clusters.compact_at {
+            self.compact_clusters();
+        }
+
+        let clusters = &mut self.clusters;
+        if clusters.texts.len() >= MAX_CLUSTERS {
+            return None;
+        }
+
+        let text = std::rc::Rc::<str>::from(text);
+        let id = ClusterId::from_index(clusters.texts.len());
+        clusters.texts.push(text.clone());
+        clusters.ids.insert(text, id);
+        Some(id)
+    }
+
+    /// The grapheme cluster of a cell with more than one character.
+    pub fn cluster(&self, cell: &GridCell) -> Option<&str> {
+        let id = cell.cluster()?;
+        self.clusters.texts.get(id.index()).map(|text| &**text)
+    }
+
+    /// Drop the grapheme clusters which no cell on the grid or in the history refers to, and give
+    /// the rest new ids.
+    fn compact_clusters(&mut self) {
+        let clusters = &mut self.clusters;
+        let history_cells = self.history.lines.iter().flat_map(|line| line.cells.iter());
+
+        let mut used = vec![false; clusters.texts.len()];
+        for cell in self.cells.iter().chain(history_cells) {
+            if let Some(id) = cell.cluster() {
+                used[id.index()] = true;
+            }
+        }
+
+        let mut new_ids = vec![None; clusters.texts.len()];
+        let mut texts = Vec::new();
+        for (index, text) in clusters.texts.iter().enumerate() {
+            if used[index] {
+                new_ids[index] = Some(ClusterId::from_index(texts.len()));
+                texts.push(text.clone());
+            }
+        }
+
+        let history_cells = self
+            .history
+            .lines
+            .iter_mut()
+            .flat_map(|line| line.cells.iter_mut());
+        for cell in self.cells.iter_mut().chain(history_cells) {
+            if let Some(id) = cell.cluster() {
+                cell.set_cluster(new_ids[id.index()]);
+            }
+        }
+
+        clusters.ids = texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| (text.clone(), ClusterId::from_index(index)))
+            .collect();
+        // Don't compact again until the table has grown as much as it shrank
+        clusters.compact_at = (texts.len() * 2).clamp(COMPACT_CLUSTERS_AT, MAX_CLUSTERS);
+        clusters.texts = texts;
+    }
+
+    /// Append the text of a cell to a string.
+    pub fn push_cell_text(&self, cell: &GridCell, text: &mut String) {
+        match self.cluster(cell) {
+            Some(cluster) => text.push_str(cluster),
+            None => text.push(cell.character()),
+        }
+    }
+
+    /// Store attributes so cells can refer to them. Attributes no longer used by any cell may be
+    /// dropped first, which changes the ids of the others, so ids must not be kept across calls
+    /// that modify the grid.
+    pub fn intern_attributes(&mut self, attributes: Attributes) -> AttributesId {
+        let table = &mut self.attributes;
+        if table.recent.0 == attributes {
+            return table.recent.1;
+        }
 
-                let row_start = columns.start as usize + row_index;
-                let row_end = columns.end as usize + row_index;
+        let id = match table.ids.get(&attributes) {
+            Some(&id) => id,
+            None => {
+                if table.sets.len() >= table.compact_at {
+                    self.compact_attributes();
+                }
 
-                self.cells[row_start..row_end].fill(cell);
+                let table = &mut self.attributes;
+                if table.sets.len() >= MAX_ATTRIBUTES {
+                    return AttributesId::DEFAULT;
+                }
+
+                let id = AttributesId(table.sets.len() as u32);
+                table.sets.push(attributes);
+                table.ids.insert(attributes, id);
+                id
+            }
+        };
+
+        self.attributes.recent = (attributes, id);
+        id
+    }
+
+    pub fn attributes(&self, cell: &GridCell) -> Attributes {
+        self.attributes
+            .sets
+            .get(cell.attributes().0 as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Drop the attributes which no cell on the grid or in the history refers to, and give the
+    /// rest new ids.
+    fn compact_attributes(&mut self) {
+        let table = &mut self.attributes;
+        let history_cells = self.history.lines.iter().flat_map(|line| line.cells.iter());
+
+        let mut used = vec![false; table.sets.len()];
+        used[AttributesId::DEFAULT.0 as usize] = true;
+        for cell in self.cells.iter().chain(history_cells) {
+            used[cell.attributes().0 as usize] = true;
+        }
+
+        let mut new_ids = vec![AttributesId::DEFAULT; table.sets.len()];
+        let mut sets = Vec::new();
+        for (index, attributes) in table.sets.iter().enumerate() {
+            if used[index] {
+                new_ids[index] = AttributesId(sets.len() as u32);
+                sets.push(*attributes);
             }
         }
+
+        let history_cells = self
+            .history
+            .lines
+            .iter_mut()
+            .flat_map(|line| line.cells.iter_mut());
+        for cell in self.cells.iter_mut().chain(history_cells) {
+            cell.set_attributes(new_ids[cell.attributes().0 as usize]);
+        }
+
+        table.ids = sets
+            .iter()
+            .enumerate()
+            .map(|(index, &attributes)| (attributes, AttributesId(index as u32)))
+            .collect();
+        table.recent = (Attributes::default(), AttributesId::DEFAULT);
+        // Don't compact again until the table has grown as much as it shrank
+        table.compact_at = (sets.len() * 2).clamp(COMPACT_ATTRIBUTES_AT, MAX_ATTRIBUTES);
+        table.sets = sets;
+    }
+
+    /// Number of lines in the history.
+    pub fn history_len(&self) -> usize {
+        self.history.lines.len()
+    }
+
+    /// Number of lines ever pushed to the history. Used to follow the history as it grows, since
+    /// the number of lines in it stops changing once it is full.
+    pub fn history_pushed(&self) -> u64 {
+        self.history.pushed
+    }
+
+    pub fn history_limit(&self) -> usize {
+        self.history.limit
+    }
+
+    /// Change the maximum number of lines in the history, dropping the oldest lines if there are
+    /// more. A limit of zero turns the history off.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.limit = limit;
+        let excess = self.history.lines.len().saturating_sub(limit);
+        self.history.lines.drain(..excess);
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.lines.clear();
+    }
+
+    /// Copy rows to the end of the history, before they are scrolled off or cleared.
+    pub fn push_history(&mut self, rows: impl std::ops::RangeBounds<u16>) {
+        if self.history.limit == 0 {
+            return;
+        }
+
+        for row in into_exclusive_range(rows, self.rows) {
+            let size = self.line_size(row);
+            let wrapped = self.is_wrapped(row);
+            let start = self.storage_row(row) * self.cols as usize;
+            let cells = &self.cells[start..start + self.cols as usize];
+
+            let line = if self.history.lines.len() >= self.history.limit {
+                // Reuse the allocation of the oldest line when the width hasn't changed
+                let mut oldest = self.history.lines.pop_front().unwrap();
+                if oldest.cells.len() == cells.len() {
+                    oldest.cells.copy_from_slice(cells);
+                    oldest.size = size;
+                    oldest.wrapped = wrapped;
+                    oldest
+                } else {
+                    HistoryLine {
+                        cells: cells.into(),
+                        size,
+                        wrapped,
+                    }
+                }
+            } else {
+                HistoryLine {
+                    cells: cells.into(),
+                    size,
+                    wrapped,
+                }
+            };
+
+            self.history.lines.push_back(line);
+            self.history.pushed += 1;
+        }
+    }
+
+    /// A space without any colors or styles that would make it visible.
+    fn is_blank(&self, cell: &GridCell) -> bool {
+        if cell.character() != ' ' || cell.cluster().is_some() || cell.width() != CellWidth::Single
+        {
+            return false;
+        }
+
+        let attributes = self.attributes(cell);
+        attributes.background == crate::color::DEFAULT_BACKGROUND && attributes.style.is_empty()
+    }
+
+    /// Returns `true` if every cell in the row is a blank space.
+    pub fn is_row_blank(&self, row: u16) -> bool {
+        self.row_cells(row).iter().all(|cell| self.is_blank(cell))
+    }
+
+    /// Mark every row as changed, such as when the grid is shown in place of another one.
+    pub fn damage_all(&mut self) {
+        self.damaged_all = true;
     }
 
-    pub fn copy_rows(&mut self, rows: impl std::ops::RangeBounds<u16>, dst_row: u16) {
+    fn damage_row(&mut self, row: u16) {
+        self.damaged_rows[row as usize] = true;
+    }
+
+    /// The rows which changed since the damage was last taken. Only changes which are visible
+    /// count, so wrapping and the history are left out.
+    pub fn take_damage(&mut self) -> Damage {
+        let damage = if self.damaged_all {
+            Damage::All
+        } else {
+            let rows = (0..self.rows).filter(|&row| self.damaged_rows[row as usize]);
+            Damage::Rows(rows.collect())
+        };
+
+        self.damaged_all = false;
+        self.damaged_rows.fill(false);
+        damage
+    }
+
+    /// Move the rows in `rows` up by `count`. The rows at the top come back at the bottom, where
+    /// they are usually cleared. Takes time in proportion to the number of rows moved, or none if
+    /// they are the whole grid.
+    pub fn rotate_rows_up(&mut self, rows: impl std::ops::RangeBounds<u16>, count: u16) {
         let rows = into_exclusive_range(rows, self.rows);
+        let count = count % (rows.len() as u16).max(1);
+        if count == 0 {
+            return;
+        }
 
-        let row_start = rows.start as usize * self.cols as usize;
-        let row_end = rows.end as usize * self.cols as usize;
+        if rows == (0..self.rows) {
+            self.damaged_all = true;
+            self.first_row =
+                ((self.first_row as usize + count as usize) % self.rows as usize) as u16;
+            return;
+        }
 
-        let dst_start = dst_row as usize * self.cols as usize;
+        for row in rows.clone() {
+            self.damage_row(row);
+        }
+
+        // Rotating is reversing both parts, and then the whole
+        self.reverse_rows(rows.start..rows.start + count);
+        self.reverse_rows(rows.start + count..rows.end);
+        self.reverse_rows(rows);
+    }
+
+    /// Move the rows in `rows` down by `count`. The rows at the bottom come back at the top.
+    pub fn rotate_rows_down(&mut self, rows: impl std::ops::RangeBounds<u16>, count: u16) {
+        let rows = into_exclusive_range(rows, self.rows);
+        let len = rows.len() as u16;
+        if len > 0 {
+            self.rotate_rows_up(rows, len - count % len);
+        }
+    }
+
+    fn reverse_rows(&mut self, rows: std::ops::Range<u16>) {
+        let (first_row, len) = (self.first_row as usize, self.rows as usize);
+        let ring_index = |row: u16| (first_row + row as usize) % len;
 
-        self.cells.copy_within(row_start..row_end, dst_start);
+        let (mut top, mut bottom) = (rows.start, rows.end);
+        while top + 1 < bottom {
+            bottom -= 1;
+            self.storage.swap(ring_index(top), ring_index(bottom));
+            top += 1;
+        }
     }
 }
 
@@ -134,7 +1067,7 @@ impl std::ops::Index<Position> for CharacterGrid {
             self.rows,
             self.cols
         );
-        &self.cells[pos.col as usize + pos.row as usize * self.cols as usize]
+        &self.cells[pos.col as usize + self.storage_row(pos.row) * self.cols as usize]
     }
 }
 
@@ -148,7 +1081,9 @@ impl std::ops::IndexMut<Position> for CharacterGrid {
             self.rows,
             self.cols
         );
-        &mut self.cells[pos.col as usize + pos.row as usize * self.cols as usize]
+        self.damage_row(pos.row);
+        let row = self.storage_row(pos.row);
+        &mut self.cells[pos.col as usize + row * self.cols as usize]
     }
 }
 
@@ -163,7 +1098,56 @@ impl std::fmt::Debug for Position {
     }
 }
 
-fn into_exclusive_range(range: impl std::ops::RangeBounds<u16>, max: u16) -> std::ops::Range<u16> {
+/// Returns `true` if the character takes up two columns. Characters with an ambiguous width are
+/// wide if `wide_ambiguous` is set, which suits CJK text.
+pub fn is_wide(ch: char, wide_ambiguous: bool) -> bool {
+    use unicode_width::UnicodeWidthChar;
+
+    let width = if wide_ambiguous {
+        ch.width_cjk()
+    } else {
+        ch.width()
+    };
+    width == Some(2)
+}
+
+/// Returns `true` if the character takes up no columns, like combining marks and variation
+/// selectors, and is shown together with the character before it.
+pub fn is_zero_width(ch: char) -> bool {
+    use unicode_width::UnicodeWidthChar;
+    ch.width() == Some(0)
+}
+
+/// Returns `true` if the grapheme cluster takes up two columns: either its first character is
+/// wide, it asks for emoji presentation (`VS16`), or it is a flag made of two regional
+/// indicators.
+pub fn is_cluster_wide(cluster: &str, wide_ambiguous: bool) -> bool {
+    const EMOJI_PRESENTATION: char = '\u{FE0F}';
+    const REGIONAL_INDICATORS: std::ops::RangeInclusive<char> = '\u{1F1E6}'..='\u{1F1FF}';
+
+    let mut chars = cluster.chars();
+    let first = match chars.next() {
+        Some(first) => first,
+        None => return false,
+    };
+
+    is_wide(first, wide_ambiguous)
+        || cluster.contains(EMOJI_PRESENTATION)
+        || (REGIONAL_INDICATORS.contains(&first)
+            && chars.any(|ch| REGIONAL_INDICATORS.contains(&ch)))
+}
+
+/// Returns `true` if `last` is a spacer left at the end of a row because a wide character did
+/// not fit, rather than the right half of a wide character.
+fn is_leading_spacer(before: &[GridCell], last: &GridCell) -> bool {
+    let after_wide = matches!(before.last(), Some(cell) if cell.width() == CellWidth::Wide);
+    last.width() == CellWidth::Spacer && !after_wide
+}
+
+pub fn into_exclusive_range(
+    range: impl std::ops::RangeBounds<u16>,
+    max: u16,
+) -> std::ops::Range<u16> {
     let start = match range.start_bound() {
         std::ops::Bound::Included(index) => *index,
         std::ops::Bound::Excluded(index) => *index + 1,
@@ -178,3 +1162,250 @@ fn into_exclusive_range(range: impl std::ops::RangeBounds<u16>, max: u16) -> std
 
     start.min(max)..end.min(max)
 }
+
+#[test]
+fn keep_history_within_limit() {
+    let mut grid = CharacterGrid::from_lines(&["a".to_owned(), "b".to_owned()], [2, 3]);
+    grid.set_history_limit(3);
+
+    grid.push_history(..);
+    grid.push_history(..1);
+    assert_eq!(grid.history_len(), 3);
+    assert_eq!(grid.history.lines[0].cells[0].character(), 'a');
+    assert_eq!(grid.history.lines[2].cells[0].character(), 'a');
+
+    grid.push_history(1..);
+    assert_eq!(grid.history_len(), 3);
+    assert_eq!(grid.history.lines[0].cells[0].character(), 'b');
+    assert_eq!(grid.history.lines[2].cells[0].character(), 'b');
+    assert_eq!(grid.history_pushed(), 4);
+
+    assert_eq!(grid.view_row(1, 0).cells[0].character(), 'b');
+    assert_eq!(grid.view_row(1, 1).cells[0].character(), 'a');
+    assert_eq!(grid.view_row(0, 1).cells[0].character(), 'b');
+
+    grid.resize(4, 5);
+    assert_eq!(grid.history_len(), 3);
+    assert_eq!(grid[Position::new(1, 0)].character(), 'b');
+    assert!(grid.is_row_blank(3));
+
+    grid.set_history_limit(1);
+    assert_eq!(grid.history_len(), 1);
+    grid.clear_history();
+    assert_eq!(grid.history_len(), 0);
+}
+
+#[test]
+fn reflow_wrapped_lines() {
+    fn text(grid: &CharacterGrid, offset: usize) -> Vec<String> {
+        (0..grid.rows())
+            .map(|row| {
+                let cells = grid.view_row(offset, row).cells;
+                cells
+                    .iter()
+                    .map(|cell| cell.character())
+                    .collect::<String>()
+            })
+            .collect()
+    }
+
+    let lines = ["abcd", "ef", "gh"]
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    let mut grid = CharacterGrid::from_lines(&lines, [3, 4]);
+    grid.set_history_limit(10);
+    grid.set_wrapped(0, true);
+
+    let cursor = grid.reflow(3, 3, Position::new(2, 2));
+    assert_eq!(text(&grid, 0), ["abc", "def", "gh "]);
+    assert_eq!(cursor, Position::new(2, 2));
+
+    let cursor = grid.reflow(2, 6, cursor);
+    assert_eq!(text(&grid, 0), ["abcdef", "gh    "]);
+    assert_eq!(cursor, Position::new(1, 2));
+
+    // Shrinking moves lines to the history, and growing brings them back
+    let cursor = grid.reflow(1, 6, cursor);
+    assert_eq!(text(&grid, 1), ["abcdef"]);
+    assert_eq!(cursor, Position::new(0, 2));
+
+    let cursor = grid.reflow(3, 3, cursor);
+    assert_eq!(grid.history_len(), 0);
+    assert_eq!(text(&grid, 0), ["abc", "def", "gh "]);
+    assert_eq!(cursor, Position::new(2, 2));
+
+    assert_eq!(grid.view_text(0), "abcdef\ngh\n");
+
+    // A pending wrap stays pending
+    let mut grid = CharacterGrid::from_lines(&lines, [1, 4]);
+    let cursor = grid.reflow(2, 2, Position::new(0, 4));
+    assert_eq!(text(&grid, 0), ["ab", "cd"]);
+    assert_eq!(cursor, Position::new(1, 2));
+}
+
+#[test]
+fn wide_characters() {
+    assert!(is_wide('中', false));
+    assert!(!is_wide('a', true));
+    assert!(!is_wide('α', false));
+    assert!(is_wide('α', true));
+    assert!(is_cluster_wide("❤\u{FE0F}", false));
+    assert!(is_cluster_wide("🇸🇪", false));
+    assert!(!is_cluster_wide("e\u{301}", false));
+    assert!(is_zero_width('\u{301}'));
+    assert!(is_zero_width('\u{FE0F}'));
+    assert!(!is_zero_width('a'));
+
+    let mut wide = GridCell::new('中', AttributesId::DEFAULT);
+    wide.set_width(CellWidth::Wide);
+    let mut spacer = GridCell::empty();
+    spacer.set_width(CellWidth::Spacer);
+
+    let mut grid = CharacterGrid::new(2, 3);
+    grid.set_history_limit(10);
+    grid[Position::new(0, 1)] = wide;
+    grid[Position::new(0, 2)] = spacer;
+    grid[Position::new(1, 0)] = GridCell::new('x', AttributesId::DEFAULT);
+    grid.set_wrapped(0, true);
+    assert_eq!(grid.view_text(0), " 中x\n");
+
+    // The wide character doesn't fit at the end of a row of two
+    grid.reflow(3, 2, Position::new(1, 1));
+    assert_eq!(grid[Position::new(0, 1)].width(), CellWidth::Spacer);
+    assert_eq!(grid[Position::new(1, 0)].width(), CellWidth::Wide);
+    assert_eq!(grid.view_text(0), " 中x\n");
+
+    // and is put back together when there is room again
+    grid.reflow(2, 3, Position::new(2, 1));
+    assert_eq!(grid[Position::new(0, 1)].width(), CellWidth::Wide);
+    assert_eq!(grid.view_text(0), " 中x\n");
+
+    // Overwriting either half clears the other
+    grid.clear_split_wide_characters(1, 1..2);
+    assert_eq!(grid[Position::new(1, 0)].width(), CellWidth::Single);
+}
+
+#[test]
+fn pack_cells() {
+    assert_eq!(std::mem::size_of::<GridCell>(), 8);
+
+    let mut grid = CharacterGrid::new(1, 2);
+    let cluster = grid.intern_cluster("e\u{301}");
+    let mut cell = GridCell::new('\u{10FFFF}', AttributesId(MAX_ATTRIBUTES as u32 - 1));
+    cell.set_cluster(cluster);
+    cell.set_width(CellWidth::Spacer);
+    assert_eq!(cell.character(), '\u{10FFFF}');
+    assert_eq!(cell.cluster(), cluster);
+    assert_eq!(cell.width(), CellWidth::Spacer);
+    assert_eq!(cell.attributes(), AttributesId(MAX_ATTRIBUTES as u32 - 1));
+    cell.clear_text();
+    assert_eq!(cell.character(), ' ');
+    assert_eq!(cell.cluster(), None);
+
+    // Attributes which are no longer used are dropped once there are many of them
+    let red = Attributes {
+        foreground: crate::color::Color::Rgb([255, 0, 0]),
+        ..Attributes::default()
+    };
+    let id = grid.intern_attributes(red);
+    grid[Position::new(0, 0)] = GridCell::new('r', id);
+    for blue in 0..COMPACT_ATTRIBUTES_AT {
+        grid.intern_attributes(Attributes {
+            foreground: crate::color::Color::Rgb([0, 0, blue as u8]),
+            background: crate::color::Color::Rgb([0, (blue >> 8) as u8, 0]),
+            ..Attributes::default()
+        });
+    }
+    assert!(grid.attributes.sets.len() < COMPACT_ATTRIBUTES_AT);
+    assert_eq!(grid.attributes(&grid[Position::new(0, 0)]), red);
+    assert_eq!(
+        grid.intern_attributes(red),
+        grid[Position::new(0, 0)].attributes()
+    );
+    assert_eq!(
+        grid.attributes(&grid[Position::new(0, 1)]),
+        Attributes::default()
+    );
+}
+
+#[test]
+fn rotate_rows() {
+    fn text(grid: &CharacterGrid) -> String {
+        (0..grid.rows())
+            .map(|row| grid[Position::new(row, 0)].character())
+            .collect()
+    }
+
+    let lines = ["a", "b", "c", "d"]
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    let mut grid = CharacterGrid::from_lines(&lines, [4, 1]);
+
+    grid.rotate_rows_up(.., 1);
+    assert_eq!(text(&grid), "bcda");
+    grid.set_wrapped(0, true);
+
+    grid.rotate_rows_up(1..4, 2);
+    assert_eq!(text(&grid), "bacd");
+
+    // Line sizes and wrapping move with the rows
+    grid.rotate_rows_down(.., 1);
+    assert_eq!(text(&grid), "dbac");
+    assert!(grid.view_row(0, 1).wrapped);
+    assert!(!grid.view_row(0, 0).wrapped);
+
+    grid.rotate_rows_down(0..3, 4);
+    assert_eq!(text(&grid), "adbc");
+
+    grid.resize(5, 2);
+    assert_eq!(text(&grid), "adbc ");
+    assert!(grid.view_row(0, 2).wrapped);
+}
+
+#[test]
+fn track_damage() {
+    let mut grid = CharacterGrid::new(4, 3);
+    assert_eq!(grid.take_damage(), Damage::All);
+    assert_eq!(grid.take_damage(), Damage::Rows(Vec::new()));
+
+    grid[Position::new(2, 1)].set_character('x');
+    grid.fill_region(0..1, 1..2, GridCell::empty());
+    grid.set_wrapped(1, true);
+    assert_eq!(grid.take_damage(), Damage::Rows(vec![0, 2]));
+
+    grid.rotate_rows_up(1..3, 1);
+    let damage = grid.take_damage();
+    assert!(damage.contains(1) && damage.contains(2) && !damage.contains(3));
+    assert_eq!(damage.in_view(2, 4), Damage::Rows(vec![3]));
+
+    grid.rotate_rows_up(.., 1);
+    assert_eq!(grid.take_damage(), Damage::All);
+    grid.resize(2, 2);
+    assert_eq!(grid.take_damage(), Damage::All);
+}
+
+#[test]
+fn drop_unused_clusters() {
+    let mut grid = CharacterGrid::new(1, 2);
+
+    // Marks past the limit are dropped
+    let stacked = format!("e{}", "\u{301}".repeat(1000));
+    let kept = &stacked[..'e'.len_utf8() + '\u{301}'.len_utf8() * (MAX_CLUSTER_CHARS - 1)];
+    let id = grid.intern_cluster(&stacked);
+    grid[Position::new(0, 0)].set_cluster(id);
+    assert_eq!(grid.cluster(&grid[Position::new(0, 0)]), Some(kept));
+    assert_eq!(grid.intern_cluster(&stacked), id);
+
+    // Clusters which are no longer used are dropped once there are many of them
+    for count in 0..COMPACT_CLUSTERS_AT {
+        grid.intern_cluster(&format!("{}\u{301}", count));
+    }
+    assert!(grid.clusters.texts.len() < COMPACT_CLUSTERS_AT);
+    assert_eq!(grid.cluster(&grid[Position::new(0, 0)]), Some(kept));
+    assert_eq!(
+        grid.intern_cluster(kept),
+        grid[Position::new(0, 0)].cluster()
+    );
+}
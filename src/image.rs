@@ -0,0 +1,265 @@
+pub mod iterm;
+pub mod kitty;
+pub mod sixel;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Default amount of memory images may use before the oldest ones are evicted.
+pub const DEFAULT_MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+/// Pixels in RGBA order with straight (not premultiplied) alpha.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Image {
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.pixels.as_slice())
+    }
+
+    /// Number of cells needed to show the image at its own size.
+    pub fn size_in_cells(&self, cell_size: [f32; 2]) -> [f32; 2] {
+        let [cell_width, cell_height] = cell_size;
+        [
+            self.width as f32 / cell_width,
+            self.height as f32 / cell_height,
+        ]
+    }
+}
+
+impl std::fmt::Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Image")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+/// Identifies an image in an `ImageStore`. Newer images have larger ids.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ImageId(u64);
+
+/// An image placed on the grid.
+#[derive(Debug, Copy, Clone)]
+pub struct Placement {
+    pub image: ImageId,
+
+    /// Row containing the top-left corner of the image. Becomes negative once the top of the image
+    /// has scrolled out of view.
+    pub row: i32,
+    /// Column containing the top-left corner of the image.
+    pub col: u16,
+
+    /// Number of rows covered by the image.
+    pub rows: u16,
+    /// Number of columns covered by the image.
+    pub cols: u16,
+
+    /// Images with a negative z-index are drawn below text, the rest above it. Images below
+    /// `BELOW_BACKGROUND` are drawn below cells with a non-default background.
+    pub z_index: i32,
+
+    /// Part of the image to show, in pixels: `[x, y, width, height]`.
+    pub source: [f32; 4],
+    /// Where to draw the image, in cells relative to the top-left corner of the first cell:
+    /// `[x, y, width, height]`.
+    pub destination: [f32; 4],
+
+    /// Set for placements made through the kitty graphics protocol.
+    pub kitty: Option<kitty::PlacementKey>,
+    /// Shows part of a virtual placement in placeholder cells, and is removed when they are
+    /// overwritten.
+    pub placeholder: bool,
+}
+
+/// Images with a z-index below this are drawn below the background of cells.
+pub const BELOW_BACKGROUND: i32 = -1_073_741_824;
+
+impl Placement {
+    /// Place all of an image, scaled to `size` cells.
+    pub fn new(id: ImageId, image_size: [u32; 2], size: [f32; 2]) -> Placement {
+        let [image_width, image_height] = image_size;
+        let [width, height] = size;
+
+        Placement {
+            image: id,
+            row: 0,
+            col: 0,
+            rows: cells_covered(height),
+            cols: cells_covered(width),
+            z_index: 0,
+            source: [0.0, 0.0, image_width as f32, image_height as f32],
+            destination: [0.0, 0.0, width, height],
+            kitty: None,
+            placeholder: false,
+        }
+    }
+
+    /// Returns `true` if the placement covers the given cell.
+    pub fn covers(&self, row: u16, col: u16) -> bool {
+        self.row <= i32::from(row)
+            && i32::from(row) < self.end_row()
+            && self.col <= col
+            && col < self.col.saturating_add(self.cols)
+    }
+
+    /// One past the last row covered by the image.
+    pub fn end_row(&self) -> i32 {
+        self.row + i32::from(self.rows)
+    }
+}
+
+/// Number of cells needed to cover a length measured in cells.
+pub fn cells_covered(length: f32) -> u16 {
+    length.ceil().clamp(1.0, f32::from(u16::MAX)) as u16
+}
+
+/// Decode a PNG, JPEG or GIF file. Only the first frame of animations is decoded. Images whose
+/// pixels would take up more than `memory_limit` bytes are rejected before they are decoded.
+pub fn decode(bytes: &[u8], memory_limit: usize) -> Result<Image, ::image::ImageError> {
+    use ::image::error::{ImageError, LimitError, LimitErrorKind};
+
+    let reader = || ::image::io::Reader::new(std::io::Cursor::new(bytes)).with_guessed_format();
+
+    let (width, height) = reader()?.into_dimensions()?;
+    let size = (width as usize)
+        .saturating_mul(height as usize)
+        .saturating_mul(4);
+    if size > memory_limit {
+        let error = LimitError::from_kind(LimitErrorKind::InsufficientMemory);
+        return Err(ImageError::Limits(error));
+    }
+
+    let decoded = reader()?.decode()?.to_rgba8();
+    let (width, height) = decoded.dimensions();
+
+    let pixels = decoded
+        .into_raw()
+        .chunks_exact(4)
+        .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]])
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Holds the pixels of every image, and evicts the oldest images once they use too much memory.
+pub struct ImageStore {
+    images: BTreeMap<ImageId, StoredImage>,
+    next_id: u64,
+    memory_usage: usize,
+    memory_limit: usize,
+}
+
+struct StoredImage {
+    image: Arc<Image>,
+    /// The image is removed as soon as no placement refers to it.
+    transient: bool,
+}
+
+impl ImageStore {
+    pub fn new(memory_limit: usize) -> ImageStore {
+        ImageStore {
+            images: BTreeMap::new(),
+            next_id: 0,
+            memory_usage: 0,
+            memory_limit,
+        }
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = limit;
+        self.evict(0);
+    }
+
+    /// Insert an image that is kept until it is explicitly removed (or evicted). Returns `None` if
+    /// the image alone exceeds the memory limit.
+    pub fn insert(&mut self, image: Image) -> Option<ImageId> {
+        self.insert_stored(image, false)
+    }
+
+    /// Insert an image that is removed by `collect_garbage` once it is no longer placed anywhere.
+    pub fn insert_transient(&mut self, image: Image) -> Option<ImageId> {
+        self.insert_stored(image, true)
+    }
+
+    fn insert_stored(&mut self, image: Image, transient: bool) -> Option<ImageId> {
+        let size = image.size_in_bytes();
+        if size > self.memory_limit {
+            return None;
+        }
+
+        self.evict(size);
+
+        let id = ImageId(self.next_id);
+        self.next_id += 1;
+
+        self.memory_usage += size;
+        self.images.insert(
+            id,
+            StoredImage {
+                image: Arc::new(image),
+                transient,
+            },
+        );
+
+        Some(id)
+    }
+
+    /// Remove the oldest images until another `additional` bytes fit within the limit.
+    fn evict(&mut self, additional: usize) {
+        while self.memory_usage + additional > self.memory_limit {
+            let oldest = match self.images.keys().next() {
+                Some(id) => *id,
+                None => break,
+            };
+
+            debug!(?oldest, "evicting image");
+            self.remove(oldest);
+        }
+    }
+
+    pub fn get(&self, id: ImageId) -> Option<&Arc<Image>> {
+        self.images.get(&id).map(|stored| &stored.image)
+    }
+
+    pub fn contains(&self, id: ImageId) -> bool {
+        self.images.contains_key(&id)
+    }
+
+    pub fn remove(&mut self, id: ImageId) {
+        if let Some(stored) = self.images.remove(&id) {
+            self.memory_usage -= stored.image.size_in_bytes();
+        }
+    }
+
+    /// Remove all transient images which are not used by any of the placements.
+    pub fn collect_garbage<'a>(&mut self, placements: impl IntoIterator<Item = &'a Placement>) {
+        let used = placements
+            .into_iter()
+            .map(|placement| placement.image)
+            .collect::<std::collections::HashSet<_>>();
+
+        let unused = self
+            .images
+            .iter()
+            .filter(|(id, stored)| stored.transient && !used.contains(id))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for id in unused {
+            self.remove(id);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Inline images of iTerm2 (<https://iterm2.com/documentation-images.html>).
+
+/// Width or height requested for an image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dimension {
+    Auto,
+    Cells(u32),
+    Pixels(u32),
+    /// Percentage of the width or height of the screen.
+    Percent(u32),
+}
+
+impl Dimension {
+    fn parse(value: &[u8]) -> Result<Dimension, &'static str> {
+        let number = |digits: &[u8]| -> Result<u32, &'static str> {
+            std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| digits.parse().ok())
+                .ok_or("invalid dimension")
+        };
+
+        let dimension = match value {
+            b"auto" => Dimension::Auto,
+            [digits @ .., b'p', b'x'] => Dimension::Pixels(number(digits)?),
+            [digits @ .., b'%'] => Dimension::Percent(number(digits)?),
+            digits => Dimension::Cells(number(digits)?),
+        };
+
+        // A size of zero would hide the image
+        match dimension {
+            Dimension::Cells(0) | Dimension::Pixels(0) | Dimension::Percent(0) => {
+                Ok(Dimension::Auto)
+            }
+            dimension => Ok(dimension),
+        }
+    }
+
+    /// Length in cells, given the size of a cell and of the screen along the same axis.
+    fn in_cells(self, cell: f32, screen: f32) -> Option<f32> {
+        match self {
+            Dimension::Auto => None,
+            Dimension::Cells(cells) => Some(cells as f32),
+            Dimension::Pixels(pixels) => Some(pixels as f32 / cell),
+            Dimension::Percent(percent) => Some(screen * percent as f32 / 100.0),
+        }
+    }
+}
+
+/// A file sent with `OSC 1337 ; File=`.
+#[derive(Debug)]
+pub struct File {
+    /// The file should be displayed instead of downloaded.
+    pub inline: bool,
+    pub width: Dimension,
+    pub height: Dimension,
+    pub preserve_aspect_ratio: bool,
+    /// Decoded contents of the file.
+    pub data: Vec<u8>,
+}
+
+impl File {
+    /// Parse the arguments and contents of a file: `key=value;...:base64`.
+    pub fn parse(file: &[u8]) -> Result<File, &'static str> {
+        let separator = file
+            .iter()
+            .position(|&byte| byte == b':')
+            .ok_or("missing file contents")?;
+        let (arguments, contents) = (&file[..separator], &file[separator + 1..]);
+
+        let mut parsed = File {
+            inline: false,
+            width: Dimension::Auto,
+            height: Dimension::Auto,
+            preserve_aspect_ratio: true,
+            data: Vec::new(),
+        };
+
+        for argument in arguments.split(|&byte| byte == b';') {
+            let separator = argument.iter().position(|&byte| byte == b'=');
+            let (key, value) = match separator {
+                Some(index) => (&argument[..index], &argument[index + 1..]),
+                None => continue,
+            };
+
+            match key {
+                b"inline" => parsed.inline = value == b"1",
+                b"width" => parsed.width = Dimension::parse(value)?,
+                b"height" => parsed.height = Dimension::parse(value)?,
+                b"preserveAspectRatio" => parsed.preserve_aspect_ratio = value != b"0",
+                // The name and size are only used for downloads
+                _ => {}
+            }
+        }
+
+        parsed.data = base64::decode(contents).map_err(|_| "invalid base64 data")?;
+
+        Ok(parsed)
+    }
+
+    /// Size of the image in cells. Images without a size are shrunk to fit the width of the
+    /// screen, and no image is larger than the screen, so that placing one can't scroll more than
+    /// a screen of lines into the history. `screen_size` is the number of columns and rows.
+    pub fn size_in_cells(
+        &self,
+        image: &super::Image,
+        cell_size: [f32; 2],
+        screen_size: [f32; 2],
+    ) -> [f32; 2] {
+        let [natural_width, natural_height] = image.size_in_cells(cell_size);
+        let aspect_ratio = natural_width / natural_height;
+
+        let width = self.width.in_cells(cell_size[0], screen_size[0]);
+        let height = self.height.in_cells(cell_size[1], screen_size[1]);
+
+        let [width, height] = match (width, height) {
+            (None, None) => {
+                let scale = (screen_size[0] / natural_width).min(1.0);
+                [natural_width * scale, natural_height * scale]
+            }
+            (Some(width), None) if self.preserve_aspect_ratio => [width, width / aspect_ratio],
+            (Some(width), None) => [width, natural_height],
+            (None, Some(height)) if self.preserve_aspect_ratio => [height * aspect_ratio, height],
+            (None, Some(height)) => [natural_width, height],
+            (Some(width), Some(height)) if self.preserve_aspect_ratio => {
+                let scale = (width / natural_width).min(height / natural_height);
+                [natural_width * scale, natural_height * scale]
+            }
+            (Some(width), Some(height)) => [width, height],
+        };
+
+        if self.preserve_aspect_ratio {
+            let scale = (screen_size[0] / width)
+                .min(screen_size[1] / height)
+                .min(1.0);
+            [width * scale, height * scale]
+        } else {
+            [width.min(screen_size[0]), height.min(screen_size[1])]
+        }
+    }
+}
+
+#[test]
+fn parse_file_arguments() {
+    let file = File::parse(b"name=eC5wbmc=;size=4;inline=1;width=10;height=50%:AAEC").unwrap();
+    assert!(file.inline);
+    assert_eq!(file.width, Dimension::Cells(10));
+    assert_eq!(file.height, Dimension::Percent(50));
+    assert!(file.preserve_aspect_ratio);
+    assert_eq!(file.data, [0, 1, 2]);
+
+    let file = File::parse(b"width=20px;height=auto;preserveAspectRatio=0:").unwrap();
+    assert!(!file.inline);
+    assert_eq!(file.width, Dimension::Pixels(20));
+    assert_eq!(file.height, Dimension::Auto);
+    assert!(!file.preserve_aspect_ratio);
+
+    // A size of zero is the same as no size
+    assert_eq!(File::parse(b"width=0:").unwrap().width, Dimension::Auto);
+
+    assert!(File::parse(b"inline=1").is_err());
+    assert!(File::parse(b"width=wide:").is_err());
+    assert!(File::parse(b"inline=1:!!").is_err());
+}
+
+#[test]
+fn size_files_in_cells() {
+    // 40x20 pixels, or 4x2 cells of 10x10 pixels, on a screen of 80x24 cells
+    let image = super::Image {
+        width: 40,
+        height: 20,
+        pixels: vec![[0; 4]; 40 * 20],
+    };
+    let size = |arguments: &str| {
+        let file = File::parse(format!("{}:", arguments).as_bytes()).unwrap();
+        file.size_in_cells(&image, [10.0, 10.0], [80.0, 24.0])
+    };
+
+    assert_eq!(size(""), [4.0, 2.0]);
+    assert_eq!(size("width=8"), [8.0, 4.0]);
+    assert_eq!(size("width=8;preserveAspectRatio=0"), [8.0, 2.0]);
+    assert_eq!(size("height=200px"), [40.0, 20.0]);
+    assert_eq!(size("height=50%;preserveAspectRatio=0"), [4.0, 12.0]);
+    assert_eq!(size("width=8;height=8"), [8.0, 4.0]);
+    assert_eq!(size("width=8;height=8;preserveAspectRatio=0"), [8.0, 8.0]);
+
+    // Images without a size are shrunk to the width of the screen
+    let wide = super::Image {
+        width: 1600,
+        height: 100,
+        pixels: Vec::new(),
+    };
+    let file = File::parse(b":").unwrap();
+    assert_eq!(
+        file.size_in_cells(&wide, [10.0, 10.0], [80.0, 24.0]),
+        [80.0, 5.0]
+    );
+
+    // Sizes larger than the screen are clamped to it
+    assert_eq!(size("height=99999"), [48.0, 24.0]);
+    assert_eq!(
+        size("width=1000;height=99999;preserveAspectRatio=0"),
+        [80.0, 24.0]
+    );
+}
@@ -0,0 +1,602 @@
+//! Parsing and decoding for the kitty graphics protocol
+//! (<https://sw.kovidgoyal.net/kitty/graphics-protocol/>).
+
+use std::collections::HashMap;
+
+/// Cells containing this character show part of a virtual placement.
+pub const PLACEHOLDER: char = '\u{10EEEE}';
+
+/// Largest width and height of an image.
+pub const MAX_DIMENSION: u32 = 10_000;
+
+/// Identifies a placement by the image and placement ids chosen by the application.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PlacementKey {
+    pub image: u32,
+    pub placement: u32,
+}
+
+/// Error reported back to the application as `CODE:message`.
+#[derive(Debug)]
+pub struct Error {
+    code: &'static str,
+    message: String,
+}
+
+impl Error {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Error {
+        Error {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Error {
+        Error::new("EINVAL", message)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.code, self.message)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    Transmit,
+    TransmitAndDisplay,
+    Place,
+    Delete,
+    Query,
+    /// Animation actions and anything unknown.
+    Unsupported(u8),
+}
+
+/// A single graphics command. Keys which are not given keep the defaults of the protocol.
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub action: Action,
+    /// 1 suppresses `OK` responses, 2 suppresses errors as well.
+    pub quiet: u8,
+
+    /// 24 for RGB, 32 for RGBA and 100 for PNG.
+    pub format: u32,
+    /// How the data is transmitted: `d`irectly, in a `f`ile, `t`emporary file or `s`hared memory.
+    pub medium: u8,
+    /// The data is compressed with zlib.
+    pub compressed: bool,
+    /// More chunks of the payload follow.
+    pub more: bool,
+
+    /// Size of raw pixel data.
+    pub width: u32,
+    pub height: u32,
+
+    /// Number of bytes to read from a file, and where to start.
+    pub size: u32,
+    pub offset: u32,
+
+    pub id: u32,
+    pub number: u32,
+    pub placement_id: u32,
+
+    /// Part of the image to display, in pixels: `[x, y, width, height]`. Zero width or height
+    /// extends to the edge of the image. Also the cell given to some delete commands.
+    pub source: [u32; 4],
+    /// Offset of the image within the first cell, in pixels.
+    pub cell_offset: [u32; 2],
+    /// Number of cells to scale the image to. Zero keeps the aspect ratio.
+    pub cols: u32,
+    pub rows: u32,
+
+    pub move_cursor: bool,
+    /// Create a virtual placement, shown by placeholder characters.
+    pub virtual_placement: bool,
+    pub z_index: i32,
+
+    /// Which placements to delete. Uppercase also frees the image data.
+    pub delete: u8,
+
+    /// Base64 encoded data.
+    pub payload: Vec<u8>,
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Command {
+            action: Action::Transmit,
+            quiet: 0,
+            format: 32,
+            medium: b'd',
+            compressed: false,
+            more: false,
+            width: 0,
+            height: 0,
+            size: 0,
+            offset: 0,
+            id: 0,
+            number: 0,
+            placement_id: 0,
+            source: [0; 4],
+            cell_offset: [0; 2],
+            cols: 0,
+            rows: 0,
+            move_cursor: true,
+            virtual_placement: false,
+            z_index: 0,
+            delete: b'a',
+            payload: Vec::new(),
+        }
+    }
+}
+
+impl Command {
+    /// Parse the control data and payload of a command: `key=value,...;payload`.
+    pub fn parse(data: &[u8]) -> Result<Command, Error> {
+        let (control, payload) = match data.iter().position(|&byte| byte == b';') {
+            Some(index) => (&data[..index], &data[index + 1..]),
+            None => (data, &[][..]),
+        };
+
+        let mut command = Command {
+            payload: payload.to_vec(),
+            ..Command::default()
+        };
+
+        for pair in control.split(|&byte| byte == b',') {
+            let (key, value) = match pair {
+                [] => continue,
+                [key, b'=', value @ ..] => (*key, value),
+                _ => return Err(Error::invalid("malformed control data")),
+            };
+
+            match key {
+                b'a' => {
+                    command.action = match single_byte(value)? {
+                        b't' => Action::Transmit,
+                        b'T' => Action::TransmitAndDisplay,
+                        b'p' => Action::Place,
+                        b'd' => Action::Delete,
+                        b'q' => Action::Query,
+                        other => Action::Unsupported(other),
+                    }
+                }
+                b'q' => command.quiet = number(value)?,
+                b'f' => command.format = number(value)?,
+                b't' => command.medium = single_byte(value)?,
+                b'o' => command.compressed = single_byte(value)? == b'z',
+                b'm' => command.more = number::<u8>(value)? == 1,
+                b's' => command.width = number(value)?,
+                b'v' => command.height = number(value)?,
+                b'S' => command.size = number(value)?,
+                b'O' => command.offset = number(value)?,
+                b'i' => command.id = number(value)?,
+                b'I' => command.number = number(value)?,
+                b'p' => command.placement_id = number(value)?,
+                b'x' => command.source[0] = number(value)?,
+                b'y' => command.source[1] = number(value)?,
+                b'w' => command.source[2] = number(value)?,
+                b'h' => command.source[3] = number(value)?,
+                b'X' => command.cell_offset[0] = number(value)?,
+                b'Y' => command.cell_offset[1] = number(value)?,
+                b'c' => command.cols = number(value)?,
+                b'r' => command.rows = number(value)?,
+                b'C' => command.move_cursor = number::<u8>(value)? == 0,
+                b'U' => command.virtual_placement = number::<u8>(value)? == 1,
+                b'z' => command.z_index = number(value)?,
+                b'd' => command.delete = single_byte(value)?,
+                _ => debug!(key = %char::from(key), "unknown graphics key"),
+            }
+        }
+
+        Ok(command)
+    }
+}
+
+fn single_byte(value: &[u8]) -> Result<u8, Error> {
+    match value {
+        [byte] => Ok(*byte),
+        _ => Err(Error::invalid("expected a single character")),
+    }
+}
+
+fn number<T: std::str::FromStr>(value: &[u8]) -> Result<T, Error> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| Error::invalid("expected a number"))
+}
+
+/// Read and decode the image transmitted by a command. At most `memory_limit` bytes of pixels
+/// are decoded.
+pub fn load_image(command: &Command, memory_limit: usize) -> Result<super::Image, Error> {
+    let payload =
+        base64::decode(&command.payload).map_err(|_| Error::invalid("invalid base64 data"))?;
+
+    let data = match command.medium {
+        b'd' => payload,
+        b'f' => read_file(&file_path(payload)?, command, memory_limit)?,
+        b't' => {
+            let path = file_path(payload)?;
+            if !is_temporary_file(&path) {
+                return Err(Error::invalid("not a temporary file"));
+            }
+
+            let data = read_file(&path, command, memory_limit);
+            if let Err(error) = std::fs::remove_file(&path) {
+                warn!(?path, %error, "failed to remove temporary file");
+            }
+            data?
+        }
+        b's' => return Err(Error::invalid("shared memory is not supported")),
+        _ => return Err(Error::invalid("unknown transmission medium")),
+    };
+
+    let data = if command.compressed {
+        miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&data, memory_limit)
+            .map_err(|_| Error::invalid("invalid zlib data"))?
+    } else {
+        data
+    };
+
+    let image = match command.format {
+        24 => raw_pixels(&data, [command.width, command.height], 3, memory_limit)?,
+        32 => raw_pixels(&data, [command.width, command.height], 4, memory_limit)?,
+        100 => super::decode(&data, memory_limit)
+            .map_err(|error| Error::new("EBADPNG", error.to_string()))?,
+        _ => return Err(Error::invalid("unknown format")),
+    };
+
+    if image.width > MAX_DIMENSION || image.height > MAX_DIMENSION {
+        return Err(Error::invalid("image is too large"));
+    }
+
+    Ok(image)
+}
+
+fn raw_pixels(
+    data: &[u8],
+    [width, height]: [u32; 2],
+    channels: usize,
+    memory_limit: usize,
+) -> Result<super::Image, Error> {
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(Error::invalid("invalid image size"));
+    }
+
+    let pixel_count = width as usize * height as usize;
+    if pixel_count * 4 > memory_limit {
+        return Err(Error::new("ENOMEM", "image exceeds the memory limit"));
+    }
+    if data.len() < pixel_count * channels {
+        return Err(Error::new("ENODATA", "insufficient image data"));
+    }
+
+    let pixels = data
+        .chunks_exact(channels)
+        .take(pixel_count)
+        .map(|pixel| match *pixel {
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        })
+        .collect();
+
+    Ok(super::Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn file_path(payload: Vec<u8>) -> Result<std::path::PathBuf, Error> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(std::ffi::OsString::from_vec(payload).into())
+}
+
+/// Read a regular file, up to `memory_limit` bytes of it.
+fn read_file(
+    path: &std::path::Path,
+    command: &Command,
+    memory_limit: usize,
+) -> Result<Vec<u8>, Error> {
+    use std::io::{Read, Seek};
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let not_found = |error: std::io::Error| Error::new("EBADF", error.to_string());
+
+    // Links are resolved, so that they can't lead to devices or files of the kernel
+    let path = path.canonicalize().map_err(not_found)?;
+    let system_directories = ["/proc", "/sys", "/dev"];
+    let in_system_directory = system_directories
+        .iter()
+        .any(|directory| path.starts_with(directory));
+    if in_system_directory && !path.starts_with("/dev/shm") {
+        return Err(Error::invalid("not a regular file"));
+    }
+
+    // Opening a FIFO would block until something writes to it, so check before opening it, and
+    // don't wait if it was replaced by one in the meantime
+    if !std::fs::metadata(&path).map_err(not_found)?.is_file() {
+        return Err(Error::invalid("not a regular file"));
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(nix::libc::O_NONBLOCK)
+        .open(&path)
+        .map_err(not_found)?;
+    if !file.metadata().map_err(not_found)?.is_file() {
+        return Err(Error::invalid("not a regular file"));
+    }
+
+    file.seek(std::io::SeekFrom::Start(u64::from(command.offset)))
+        .map_err(not_found)?;
+
+    // Read one byte more than the limit, to find out if the file is too large
+    let limit = (memory_limit as u64).saturating_add(1);
+    let size = if command.size == 0 {
+        limit
+    } else {
+        u64::from(command.size).min(limit)
+    };
+
+    let mut data = Vec::new();
+    file.take(size).read_to_end(&mut data).map_err(not_found)?;
+    if data.len() > memory_limit {
+        return Err(Error::invalid("file is too large"));
+    }
+
+    Ok(data)
+}
+
+/// Temporary files are deleted after reading, so they must look like they were created for this
+/// purpose.
+fn is_temporary_file(path: &std::path::Path) -> bool {
+    let named_for_protocol = match path.file_name() {
+        Some(name) => name.to_string_lossy().contains("tty-graphics-protocol"),
+        None => false,
+    };
+
+    let directory = match path.parent().and_then(|parent| parent.canonicalize().ok()) {
+        Some(directory) => directory,
+        None => return false,
+    };
+
+    let temporary_directories = [
+        std::env::temp_dir(),
+        std::path::PathBuf::from("/tmp"),
+        std::path::PathBuf::from("/dev/shm"),
+    ];
+    let in_temporary_directory = temporary_directories
+        .iter()
+        .filter_map(|temporary| temporary.canonicalize().ok())
+        .any(|temporary| directory.starts_with(temporary));
+
+    named_for_protocol && in_temporary_directory
+}
+
+/// Images and virtual placements created through the protocol.
+#[derive(Debug, Default)]
+pub struct KittyImages {
+    /// Images by the id chosen by the application, or assigned by us.
+    pub images: HashMap<u32, KittyImage>,
+    pub virtual_placements: HashMap<PlacementKey, VirtualPlacement>,
+
+    /// The first chunk of a transmission, with the payload of the chunks received so far.
+    pub pending: Option<Command>,
+
+    /// The most recent placeholder cell, while its diacritics may still be arriving.
+    pub placeholder: Option<Placeholder>,
+    /// The placeholder before it, which it may continue.
+    pub previous_placeholder: Option<Placeholder>,
+
+    last_assigned_id: u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct KittyImage {
+    pub image: super::ImageId,
+    /// Image number given by the application, or 0.
+    pub number: u32,
+}
+
+impl KittyImages {
+    /// Pick an id which is not used by any image.
+    pub fn unused_id(&mut self) -> u32 {
+        loop {
+            self.last_assigned_id = self.last_assigned_id.wrapping_add(1).max(1);
+            if !self.images.contains_key(&self.last_assigned_id) {
+                return self.last_assigned_id;
+            }
+        }
+    }
+
+    /// Id of the newest image with the given number.
+    pub fn id_for_number(&self, number: u32) -> Option<u32> {
+        self.images
+            .iter()
+            .filter(|(_, image)| image.number == number)
+            .max_by_key(|(_, image)| image.image)
+            .map(|(id, _)| *id)
+    }
+}
+
+/// A placement which is only shown in cells containing placeholder characters.
+#[derive(Debug, Copy, Clone)]
+pub struct VirtualPlacement {
+    pub rows: u16,
+    pub cols: u16,
+    /// Part of the image to show, in pixels.
+    pub source: [f32; 4],
+    pub z_index: i32,
+}
+
+impl VirtualPlacement {
+    /// The part of the image shown in the placeholder at `row` and `col` of the placement, as the
+    /// source in pixels and the destination in cells relative to the placeholder's cell. The
+    /// image is scaled to fit the placement and centered within it.
+    pub fn cell(&self, row: u32, col: u32, cell_size: [f32; 2]) -> Option<([f32; 4], [f32; 4])> {
+        if row >= u32::from(self.rows) || col >= u32::from(self.cols) {
+            return None;
+        }
+
+        let [cell_width, cell_height] = cell_size;
+        let [source_x, source_y, source_width, source_height] = self.source;
+
+        let area_width = f32::from(self.cols) * cell_width;
+        let area_height = f32::from(self.rows) * cell_height;
+        let scale = (area_width / source_width).min(area_height / source_height);
+
+        let left = (area_width - source_width * scale) / 2.0;
+        let top = (area_height - source_height * scale) / 2.0;
+
+        let cell_left = col as f32 * cell_width;
+        let cell_top = row as f32 * cell_height;
+
+        let x0 = left.max(cell_left);
+        let y0 = top.max(cell_top);
+        let x1 = (area_width - left).min(cell_left + cell_width);
+        let y1 = (area_height - top).min(cell_top + cell_height);
+
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+
+        let source = [
+            source_x + (x0 - left) / scale,
+            source_y + (y0 - top) / scale,
+            (x1 - x0) / scale,
+            (y1 - y0) / scale,
+        ];
+        let destination = [
+            (x0 - cell_left) / cell_width,
+            (y0 - cell_top) / cell_height,
+            (x1 - x0) / cell_width,
+            (y1 - y0) / cell_height,
+        ];
+
+        Some((source, destination))
+    }
+}
+
+/// A cell containing a placeholder character.
+#[derive(Debug, Copy, Clone)]
+pub struct Placeholder {
+    pub position: crate::grid::Position,
+    /// Lower 24 bits of the image id, given by the foreground color.
+    pub color_id: u32,
+    /// Row and column within the placement, and the most significant byte of the image id.
+    pub row: u32,
+    pub col: u32,
+    pub id_high_byte: u32,
+    /// Number of diacritics attached so far.
+    pub diacritics: u8,
+}
+
+impl Placeholder {
+    /// A placeholder written at `position` with the given foreground color. Row and column are
+    /// continued from the placeholder to the left of it, if any.
+    pub fn new(
+        position: crate::grid::Position,
+        foreground: crate::color::Color,
+        previous: Option<&Placeholder>,
+    ) -> Placeholder {
+        let color_id = match foreground {
+            crate::color::Color::Index(index) => u32::from(index),
+            crate::color::Color::Rgb([r, g, b]) => {
+                u32::from(r) << 16 | u32::from(g) << 8 | u32::from(b)
+            }
+            crate::color::Color::DefaultForeground | crate::color::Color::DefaultBackground => 0,
+        };
+
+        let continues = previous.filter(|previous| {
+            previous.color_id == color_id
+                && previous.position.row == position.row
+                && previous.position.col + 1 == position.col
+        });
+
+        match continues {
+            Some(previous) => Placeholder {
+                position,
+                color_id,
+                row: previous.row,
+                col: previous.col + 1,
+                id_high_byte: previous.id_high_byte,
+                diacritics: 0,
+            },
+            None => Placeholder {
+                position,
+                color_id,
+                row: 0,
+                col: 0,
+                id_high_byte: 0,
+                diacritics: 0,
+            },
+        }
+    }
+
+    /// Attach a diacritic, returning `false` if it isn't one that encodes a number.
+    pub fn push_diacritic(&mut self, ch: char) -> bool {
+        let value = match DIACRITICS.binary_search(&u32::from(ch)) {
+            Ok(value) => value as u32,
+            Err(_) => return false,
+        };
+
+        match self.diacritics {
+            0 => self.row = value,
+            1 => self.col = value,
+            2 => self.id_high_byte = value,
+            _ => {}
+        }
+        self.diacritics = self.diacritics.saturating_add(1);
+
+        true
+    }
+
+    pub fn image_id(&self) -> u32 {
+        self.id_high_byte << 24 | self.color_id
+    }
+}
+
+/// Combining characters encoding the numbers 0, 1, 2... in placeholders.
+const DIACRITICS: &[u32] = &[
+    0x0305, 0x030D, 0x030E, 0x0310, 0x0312, 0x033D, 0x033E, 0x033F, 0x0346, 0x034A, 0x034B, 0x034C,
+    0x0350, 0x0351, 0x0352, 0x0357, 0x035B, 0x0363, 0x0364, 0x0365, 0x0366, 0x0367, 0x0368, 0x0369,
+    0x036A, 0x036B, 0x036C, 0x036D, 0x036E, 0x036F, 0x0483, 0x0484, 0x0485, 0x0486, 0x0487, 0x0592,
+    0x0593, 0x0594, 0x0595, 0x0597, 0x0598, 0x0599, 0x059C, 0x059D, 0x059E, 0x059F, 0x05A0, 0x05A1,
+    0x05A8, 0x05A9, 0x05AB, 0x05AC, 0x05AF, 0x05C4, 0x0610, 0x0611, 0x0612, 0x0613, 0x0614, 0x0615,
+    0x0616, 0x0617, 0x0657, 0x0658, 0x0659, 0x065A, 0x065B, 0x065D, 0x065E, 0x06D6, 0x06D7, 0x06D8,
+    0x06D9, 0x06DA, 0x06DB, 0x06DC, 0x06DF, 0x06E0, 0x06E1, 0x06E2, 0x06E4, 0x06E7, 0x06E8, 0x06EB,
+    0x06EC, 0x0730, 0x0732, 0x0733, 0x0735, 0x0736, 0x073A, 0x073D, 0x073F, 0x0740, 0x0741, 0x0743,
+    0x0745, 0x0747, 0x0749, 0x074A, 0x07EB, 0x07EC, 0x07ED, 0x07EE, 0x07EF, 0x07F0, 0x07F1, 0x07F3,
+    0x0816, 0x0817, 0x0818, 0x0819, 0x081B, 0x081C, 0x081D, 0x081E, 0x081F, 0x0820, 0x0821, 0x0822,
+    0x0823, 0x0825, 0x0826, 0x0827, 0x0829, 0x082A, 0x082B, 0x082C, 0x082D, 0x0951, 0x0953, 0x0954,
+    0x0F82, 0x0F83, 0x0F86, 0x0F87, 0x135D, 0x135E, 0x135F, 0x17DD, 0x193A, 0x1A17, 0x1A75, 0x1A76,
+    0x1A77, 0x1A78, 0x1A79, 0x1A7A, 0x1A7B, 0x1A7C, 0x1B6B, 0x1B6D, 0x1B6E, 0x1B6F, 0x1B70, 0x1B71,
+    0x1B72, 0x1B73, 0x1DC0, 0x1DC1, 0x1DC3, 0x1DC4, 0x1DC5, 0x1DC6, 0x1DC7, 0x1DC8, 0x1DC9, 0x1DCB,
+    0x1DCC, 0x1DD1, 0x1DD2, 0x1DD3, 0x1DD4, 0x1DD5, 0x1DD6, 0x1DD7, 0x1DD8, 0x1DD9, 0x1DDA, 0x1DDB,
+    0x1DDC, 0x1DDD, 0x1DDE, 0x1DDF, 0x1DE0, 0x1DE1, 0x1DE2, 0x1DE3, 0x1DE4, 0x1DE5, 0x1DE6, 0x1DFE,
+    0x20D0, 0x20D1, 0x20D4, 0x20D5, 0x20D6, 0x20D7, 0x20DB, 0x20DC, 0x20E1, 0x20E7, 0x20E9, 0x20F0,
+    0x2CEF, 0x2CF0, 0x2CF1, 0x2DE0, 0x2DE1, 0x2DE2, 0x2DE3, 0x2DE4, 0x2DE5, 0x2DE6, 0x2DE7, 0x2DE8,
+    0x2DE9, 0x2DEA, 0x2DEB, 0x2DEC, 0x2DED, 0x2DEE, 0x2DEF, 0x2DF0, 0x2DF1, 0x2DF2, 0x2DF3, 0x2DF4,
+    0x2DF5, 0x2DF6, 0x2DF7, 0x2DF8, 0x2DF9, 0x2DFA, 0x2DFB, 0x2DFC, 0x2DFD, 0x2DFE, 0x2DFF, 0xA66F,
+    0xA67C, 0xA67D, 0xA6F0, 0xA6F1, 0xA8E0, 0xA8E1, 0xA8E2, 0xA8E3, 0xA8E4, 0xA8E5, 0xA8E6, 0xA8E7,
+    0xA8E8, 0xA8E9, 0xA8EA, 0xA8EB, 0xA8EC, 0xA8ED, 0xA8EE, 0xA8EF, 0xA8F0, 0xA8F1, 0xAAB0, 0xAAB2,
+    0xAAB3, 0xAAB7, 0xAAB8, 0xAABE, 0xAABF, 0xAAC1, 0xFE20, 0xFE21, 0xFE22, 0xFE23, 0xFE24, 0xFE25,
+    0xFE26, 0x10A0F, 0x10A38, 0x1D185, 0x1D186, 0x1D187, 0x1D188, 0x1D189, 0x1D1AA, 0x1D1AB,
+    0x1D1AC, 0x1D1AD, 0x1D242, 0x1D243, 0x1D244,
+];
+
+#[test]
+fn parse_chunked_transmission() {
+    let command = Command::parse(b"a=T,f=24,s=2,v=1,i=7,m=1,z=-5;AAAA").unwrap();
+
+    assert_eq!(command.action, Action::TransmitAndDisplay);
+    assert_eq!([command.format, command.width, command.height], [24, 2, 1]);
+    assert_eq!(command.id, 7);
+    assert_eq!(command.z_index, -5);
+    assert!(command.more);
+    assert_eq!(command.payload, b"AAAA");
+}
@@ -0,0 +1,300 @@
+//! Decoder for DEC sixel graphics.
+
+/// Largest number of color registers an image may use.
+pub const MAX_COLOR_REGISTERS: u16 = 1024;
+
+/// Number of color registers used unless the application asks for a different amount.
+pub const DEFAULT_COLOR_REGISTERS: u16 = 256;
+
+/// Largest width and height of a sixel image. Pixels outside this area are dropped.
+pub const MAX_DIMENSION: u32 = 8192;
+
+/// Decode the data of a sixel DCS (everything after the `q`). Returns `None` if the image does not
+/// contain any pixels, or its pixels would take up more than `memory_limit` bytes.
+pub fn decode(
+    data: &[u8],
+    transparent_background: bool,
+    color_registers: u16,
+    memory_limit: usize,
+) -> Option<super::Image> {
+    let mut decoder = Decoder::new(color_registers, memory_limit / 4);
+    decoder.feed(data);
+    decoder.finish(transparent_background)
+}
+
+struct Decoder {
+    palette: Vec<[u8; 4]>,
+    color: usize,
+
+    /// Position of the next sixel. `y` is the top row of the current band of six pixels.
+    x: u32,
+    y: u32,
+
+    /// Pixels are stored row by row, and each row only as long as needed.
+    rows: Vec<Vec<[u8; 4]>>,
+    /// Length of the longest row, or the width given by the raster attributes if that is larger.
+    width: usize,
+    /// Height given by the raster attributes. The image is higher if pixels are drawn below it.
+    min_height: usize,
+
+    /// Number of pixels the image may have. Drawing stops once the rows would need more.
+    max_pixels: usize,
+    too_large: bool,
+}
+
+impl Decoder {
+    fn new(color_registers: u16, max_pixels: usize) -> Decoder {
+        let registers = color_registers.clamp(1, MAX_COLOR_REGISTERS) as usize;
+
+        let mut palette = vec![[0, 0, 0, 255]; registers];
+        for (register, color) in palette.iter_mut().zip(DEFAULT_PALETTE.iter()) {
+            *register = *color;
+        }
+
+        Decoder {
+            palette,
+            color: 0,
+            x: 0,
+            y: 0,
+            rows: Vec::new(),
+            width: 0,
+            min_height: 0,
+            max_pixels,
+            too_large: false,
+        }
+    }
+
+    fn feed(&mut self, data: &[u8]) {
+        let mut bytes = data.iter().copied().peekable();
+
+        while let Some(byte) = bytes.next() {
+            match byte {
+                // Raster attributes: `" Pan ; Pad ; Ph ; Pv`. The size is the least size of the
+                // image, and the area which isn't drawn on is filled with the background.
+                b'"' => {
+                    let [_pan, _pad, width, height] = parse_numbers(&mut bytes);
+                    let width = (width.min(MAX_DIMENSION) as usize).max(self.width);
+                    let height = height.min(MAX_DIMENSION) as usize;
+                    if width.saturating_mul(height.max(self.rows.len())) > self.max_pixels {
+                        self.too_large = true;
+                    } else {
+                        self.width = width;
+                        self.min_height = height;
+                    }
+                }
+
+                // Color introducer: `# Pc` or `# Pc ; Pu ; Px ; Py ; Pz`
+                b'#' => {
+                    let [register, system, x, y, z] = parse_numbers(&mut bytes);
+                    let register = register as usize % self.palette.len();
+
+                    match system {
+                        1 => self.palette[register] = hls_to_rgba(x, y, z),
+                        2 => self.palette[register] = [percent(x), percent(y), percent(z), 255],
+                        _ => {}
+                    }
+
+                    self.color = register;
+                }
+
+                // Repeat introducer: `! Pn <sixel>`
+                b'!' => {
+                    let [count] = parse_numbers(&mut bytes);
+                    if let Some(sixel @ 0x3f..=0x7e) = bytes.next() {
+                        self.draw(sixel - 0x3f, count.max(1));
+                    }
+                }
+
+                // Graphics carriage return
+                b'$' => self.x = 0,
+
+                // Graphics new line
+                b'-' => {
+                    self.x = 0;
+                    self.y = self.y.saturating_add(6);
+                }
+
+                sixel @ 0x3f..=0x7e => self.draw(sixel - 0x3f, 1),
+
+                _ => {}
+            }
+        }
+    }
+
+    /// Draw a column of six pixels `count` times.
+    fn draw(&mut self, bits: u8, count: u32) {
+        let start = self.x;
+        self.x = self.x.saturating_add(count);
+
+        if bits == 0 || start >= MAX_DIMENSION || self.too_large {
+            return;
+        }
+
+        let end = self.x.min(MAX_DIMENSION) as usize;
+        let lowest = (self.y + 5).min(MAX_DIMENSION - 1) as usize;
+
+        // The image is at least as wide as its longest row and as high as its lowest one
+        let width = self.width.max(end);
+        let height = self.rows.len().max(lowest + 1).max(self.min_height);
+        if width.saturating_mul(height) > self.max_pixels {
+            self.too_large = true;
+            return;
+        }
+        self.width = width;
+
+        let color = self.palette[self.color];
+
+        for bit in 0..6 {
+            let y = self.y + bit;
+            if bits & (1 << bit) == 0 || y >= MAX_DIMENSION {
+                continue;
+            }
+
+            let y = y as usize;
+            if self.rows.len() <= y {
+                self.rows.resize_with(y + 1, Vec::new);
+            }
+
+            let row = &mut self.rows[y];
+            if row.len() < end {
+                row.resize(end, [0, 0, 0, 0]);
+            }
+            row[start as usize..end].fill(color);
+        }
+    }
+
+    fn finish(self, transparent_background: bool) -> Option<super::Image> {
+        let width = self.width as u32;
+        let height = self.rows.len().max(self.min_height) as u32;
+
+        if width == 0 || height == 0 || self.too_large {
+            return None;
+        }
+
+        let background = if transparent_background {
+            [0, 0, 0, 0]
+        } else {
+            self.palette[0]
+        };
+
+        let mut pixels = vec![background; width as usize * height as usize];
+
+        for (y, row) in self.rows.iter().enumerate() {
+            let offset = y * width as usize;
+            for (x, pixel) in row.iter().enumerate() {
+                // Pixels which have been drawn are always opaque
+                if pixel[3] != 0 {
+                    pixels[offset + x] = *pixel;
+                }
+            }
+        }
+
+        Some(super::Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// Parse up to `N` numbers separated by `;`. Missing numbers default to 0.
+fn parse_numbers<const N: usize>(
+    bytes: &mut std::iter::Peekable<impl Iterator<Item = u8>>,
+) -> [u32; N] {
+    let mut numbers = [0u32; N];
+    let mut index = 0;
+
+    while let Some(&byte) = bytes.peek() {
+        match byte {
+            b'0'..=b'9' => {
+                if let Some(number) = numbers.get_mut(index) {
+                    *number = number
+                        .saturating_mul(10)
+                        .saturating_add(u32::from(byte - b'0'));
+                }
+            }
+            b';' => index += 1,
+            _ => break,
+        }
+
+        bytes.next();
+    }
+
+    numbers
+}
+
+fn percent(value: u32) -> u8 {
+    ((value.min(100) * 255 + 50) / 100) as u8
+}
+
+/// Convert from the HLS color space used by sixels, where hue 0 is blue.
+fn hls_to_rgba(hue: u32, lightness: u32, saturation: u32) -> [u8; 4] {
+    let hue = ((hue + 240) % 360) as f32 / 360.0;
+    let lightness = lightness.min(100) as f32 / 100.0;
+    let saturation = saturation.min(100) as f32 / 100.0;
+
+    let rgb = crate::color::hsl_to_rgb([hue, saturation, lightness]);
+    let [r, g, b] = crate::color::rgb_f32_to_rgb_u8(rgb);
+    [r, g, b, 255]
+}
+
+/// The 16 colors of the VT340 in the first color registers.
+const DEFAULT_PALETTE: [[u8; 4]; 16] = {
+    const fn rgb(r: u32, g: u32, b: u32) -> [u8; 4] {
+        [
+            ((r * 255 + 50) / 100) as u8,
+            ((g * 255 + 50) / 100) as u8,
+            ((b * 255 + 50) / 100) as u8,
+            255,
+        ]
+    }
+
+    [
+        rgb(0, 0, 0),
+        rgb(20, 20, 80),
+        rgb(80, 13, 13),
+        rgb(20, 80, 20),
+        rgb(80, 20, 80),
+        rgb(20, 80, 80),
+        rgb(80, 80, 20),
+        rgb(53, 53, 53),
+        rgb(26, 26, 26),
+        rgb(33, 33, 60),
+        rgb(60, 26, 26),
+        rgb(33, 60, 33),
+        rgb(60, 33, 60),
+        rgb(33, 60, 60),
+        rgb(60, 60, 33),
+        rgb(80, 80, 80),
+    ]
+};
+
+#[test]
+fn decode_repeated_sixels() {
+    let image = decode(
+        b"\"1;1;4;6#1;2;100;0;0#1!3~-#2@",
+        true,
+        DEFAULT_COLOR_REGISTERS,
+        1024,
+    )
+    .unwrap();
+
+    // The image is at least as large as the raster attributes say, even where nothing is drawn
+    assert_eq!([image.width, image.height], [4, 7]);
+
+    let red = [255, 0, 0, 255];
+    assert_eq!(image.pixels[0], red);
+    assert_eq!(image.pixels[2 + 5 * 4], red);
+    assert_eq!(image.pixels[3], [0, 0, 0, 0]);
+    assert_eq!(image.pixels[1 + 6 * 4], [0, 0, 0, 0]);
+    assert_eq!(image.pixels[6 * 4], DEFAULT_PALETTE[2]);
+
+    // Without a transparent background, the rest is filled with the first color register
+    let image = decode(b"\"1;1;8;12#1~", false, DEFAULT_COLOR_REGISTERS, 1024).unwrap();
+    assert_eq!([image.width, image.height], [8, 12]);
+    assert_eq!(image.pixels[7 + 11 * 8], DEFAULT_PALETTE[0]);
+
+    // Images larger than the memory limit are dropped before their pixels are allocated
+    assert!(decode(b"!8192~", true, DEFAULT_COLOR_REGISTERS, 1024).is_none());
+    assert!(decode(b"\"1;1;8192;8192", true, DEFAULT_COLOR_REGISTERS, 1024).is_none());
+}
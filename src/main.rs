@@ -1,20 +1,54 @@
+mod bell;
 mod color;
+mod color_scheme;
 mod config;
 mod font;
 mod grid;
+mod image;
 mod inline;
 mod log;
+mod paste;
 mod render;
 mod screen;
+mod title;
 mod tty;
+mod viewport;
 mod window;
 
 #[macro_use]
 extern crate tracing;
 
 fn main() {
+    // Other arguments are ignored, since macOS passes some of its own, like `-psn_0_12345` when
+    // launched from the Finder
+    let mut args = std::env::args().skip(1);
+    let mut ignored_args = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg != "--dump-actions" {
+            ignored_args.push(arg);
+            continue;
+        }
+
+        let path = match args.next() {
+            Some(path) => path,
+            None => {
+                eprintln!("usage: spotty [--dump-actions <file|->]");
+                std::process::exit(2);
+            }
+        };
+        if let Err(error) = dump_actions(&path) {
+            eprintln!("spotty: {}: {}", path, error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     log::init();
 
+    if !ignored_args.is_empty() {
+        warn!(?ignored_args, "ignoring unknown arguments");
+    }
+
     let event_loop = window::EventLoop::new();
     let window = window::Window::new(
         &event_loop,
@@ -26,11 +60,13 @@ fn main() {
     let mut terminal = Terminal::new(window, event_loop.create_waker());
 
     event_loop.run(move |event| match event {
-        window::Event::Active => {}
-        window::Event::Inactive => {}
+        window::Event::Active => terminal.focus_changed(true),
+        window::Event::Inactive => terminal.focus_changed(false),
         window::Event::Resize(size) => terminal.resize(size),
         window::Event::ScaleFactorChanged => terminal.scale_factor_changed(),
+        window::Event::ColorSchemeChanged => terminal.color_scheme_changed(),
         window::Event::KeyPress(key, modifiers) => terminal.key_press(key, modifiers),
+        window::Event::Scroll(delta) => terminal.scroll(delta),
         window::Event::EventsCleared => {
             terminal.poll_input();
             terminal.render();
@@ -38,11 +74,44 @@ fn main() {
     });
 }
 
+/// Print the actions the parser decodes from a captured byte stream, together with their offsets.
+fn dump_actions(path: &str) -> std::io::Result<()> {
+    use std::io::{Read, Write};
+
+    let bytes = if path == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        std::fs::read(path)?
+    };
+
+    let mut collector = tty::action::ActionCollector::default();
+    let incomplete = collector.parse(&bytes);
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    for (offset, action) in &collector.actions {
+        writeln!(stdout, "{:>8}  {:?}", offset, action)?;
+    }
+
+    if !incomplete.is_empty() {
+        let offset = bytes.len() - incomplete.len();
+        let incomplete = tty::action::Bytes(incomplete.to_vec());
+        writeln!(stdout, "{:>8}  Incomplete({:?})", offset, incomplete)?;
+    }
+
+    Ok(())
+}
+
 fn load_font(font_size: f64, scale_factor: f64) -> font::FontCollection {
     font::Font::collection("Iosevka SS14", font_size * scale_factor).expect("failed to load font")
 }
 
 pub struct Terminal {
+    config: config::Config,
+
     pty: tty::Psuedoterminal,
 
     window: window::Window,
@@ -54,6 +123,29 @@ pub struct Terminal {
     font_size: f64,
 
     screen: screen::Screen,
+    color_scheme: color_scheme::Preference,
+    /// How far the main screen is scrolled up into the history.
+    viewport: viewport::Viewport,
+    /// Scrolled distance, in lines, that has not yet been acted upon.
+    scroll_remainder: f32,
+    /// History shown by the last frame: the scroll offset, lines pushed and length of the history.
+    rendered_history: (usize, u64, usize),
+    /// Size of the grid last reported to the shell.
+    pty_grid_size: [u16; 2],
+
+    /// Show the unsupported sequences instead of the screen.
+    show_unsupported: bool,
+    /// Outcome of the last attempt to save the unsupported sequences to a file.
+    unsupported_status: Option<String>,
+
+    /// A multiline paste waiting for confirmation.
+    pending_paste: Option<String>,
+
+    bell: bell::Bell,
+    focused: bool,
+    /// Title and icon name last given to the window.
+    window_title: String,
+    window_icon_name: String,
 
     dirty: bool,
 }
@@ -67,12 +159,26 @@ impl Terminal {
 
         let cell_size = font::cell_size(&font_collection.regular);
         let grid_size = grid::size_in_window(window.inner_size(), cell_size);
-        let screen = screen::Screen::new(grid_size);
+        let config = config::Config::default();
+
+        let mut screen = screen::Screen::new(grid_size);
+        screen.cell_size = cell_size;
+        screen.grid.set_history_limit(config.scrollback.lines);
+        screen.save_cleared_screen = config.scrollback.save_cleared_screen;
+        screen.wide_ambiguous = config.wide_ambiguous_characters;
+        screen.images.set_memory_limit(config.image_memory_limit);
+
+        let color_scheme =
+            color_scheme::Preference::new(Box::new(window::SystemColorScheme), config.color_scheme);
+        screen.color_scheme = color_scheme.current();
 
         let pty = tty::Psuedoterminal::connect(waker.clone()).unwrap();
-        pty.set_grid_size(screen.grid.size());
+        let pty_grid_size = screen.grid.size();
+        pty.set_grid_size(pty_grid_size);
 
         Terminal {
+            config,
+
             pty,
 
             window,
@@ -84,6 +190,21 @@ impl Terminal {
             font_size,
 
             screen,
+            color_scheme,
+            viewport: viewport::Viewport::default(),
+            scroll_remainder: 0.0,
+            rendered_history: (0, 0, 0),
+            pty_grid_size,
+
+            show_unsupported: false,
+            unsupported_status: None,
+
+            pending_paste: None,
+
+            bell: bell::Bell::default(),
+            focused: true,
+            window_title: String::new(),
+            window_icon_name: String::new(),
 
             dirty: true,
         }
@@ -99,17 +220,98 @@ impl Terminal {
 
     fn update_grid_size(&mut self, window_size: window::PhysicalSize) {
         let cell_size = font::cell_size(&self.font_collection.regular);
-        let new_grid_size = grid::size_in_window(window_size, cell_size);
+        let mut new_grid_size = grid::size_in_window(window_size, cell_size);
+
+        // In 132 column mode only the number of rows follows the window. The grid keeps all of its
+        // columns even if the window is too narrow for them, and the renderer scales the whole
+        // frame down to fit instead. With the default `window_operations = Deny`, `DECCOLM` never
+        // resizes the window, so this is how 132 columns are always shown.
+        if self.screen.behaviours.column_mode {
+            new_grid_size[1] = screen::WIDE_COLUMNS;
+        }
 
         let old_grid_size = self.screen.grid.size();
 
+        self.screen.cell_size = cell_size;
+
         if old_grid_size != new_grid_size {
-            self.pty.set_grid_size(new_grid_size);
             self.screen.resize_grid(new_grid_size);
+            self.sync_pty_grid_size();
             self.dirty = true;
         }
     }
 
+    /// Tell the shell about changes to the size of the grid.
+    fn sync_pty_grid_size(&mut self) {
+        let grid_size = self.screen.grid.size();
+        if grid_size != self.pty_grid_size {
+            self.pty.set_grid_size(grid_size);
+            self.pty_grid_size = grid_size;
+        }
+    }
+
+    pub fn focus_changed(&mut self, focused: bool) {
+        self.focused = focused;
+        if focused {
+            self.bell.marked = false;
+            self.update_window_title();
+        }
+    }
+
+    /// Tell the application about the preferred color scheme, if it asked to be notified. The
+    /// report is sent with the other responses.
+    pub fn color_scheme_changed(&mut self) {
+        self.screen.set_color_scheme(self.color_scheme.current());
+    }
+
+    fn ring_bell(&mut self) {
+        let config = &self.config.bell;
+        if !self.bell.ring(std::time::Instant::now(), config) {
+            return;
+        }
+
+        if !self.focused {
+            self.bell.marked = true;
+            if config.urgency_hint {
+                self.window.request_attention();
+            }
+        }
+
+        bell::run_command(&config.command);
+        self.dirty = true;
+    }
+
+    /// Fill in the title template, and show the icon name set by the application.
+    fn update_window_title(&mut self) {
+        let template = &self.config.title_template;
+        let process = if title::uses_process(template) {
+            self.pty.foreground_process()
+        } else {
+            None
+        };
+
+        let fields = title::Fields {
+            title: &self.screen.title,
+            icon_name: &self.screen.icon_name,
+            process: process.as_ref().map(|process| process.name.as_str()),
+            cwd: process.as_ref().and_then(|process| process.cwd.as_deref()),
+            cols: self.screen.grid.cols(),
+            rows: self.screen.grid.rows(),
+            bell: self.bell.marked,
+        };
+        let title = title::expand(template, &fields);
+
+        if title != self.window_title {
+            self.window.set_title(&title);
+            self.window_title = title;
+        }
+
+        if self.screen.icon_name != self.window_icon_name {
+            self.window.set_icon_name(&self.screen.icon_name);
+            self.window_icon_name = self.screen.icon_name.clone();
+        }
+    }
+
     pub fn scale_factor_changed(&mut self) {
         self.reload_font();
         self.resize(self.window.inner_size());
@@ -134,6 +336,30 @@ impl Terminal {
             modifiers.set(Modifiers::ALT, sup);
         }
 
+        if self.pending_paste.is_some() {
+            self.confirm_paste_key_press(key);
+            return;
+        }
+
+        if self.show_unsupported {
+            self.unsupported_key_press(key, modifiers);
+            return;
+        }
+
+        // The alternate screen has no history, so the keys go to the application instead
+        if !self.screen.behaviours.alternate_buffer {
+            if let Some(scroll) = viewport_scroll(key, modifiers) {
+                self.scroll_viewport(scroll);
+                return;
+            }
+        }
+
+        // Shortcuts such as changing the font size don't send anything to the shell
+        let shortcut = modifiers.contains(Modifiers::SUPER);
+        if self.config.scrollback.scroll_to_bottom_on_key_press && !shortcut {
+            self.scroll_viewport(viewport::Scroll::Bottom);
+        }
+
         match key {
             window::Key::Char(ch) => match modifiers {
                 Modifiers::EMPTY | Modifiers::SHIFT => {
@@ -149,9 +375,15 @@ impl Terminal {
                     self.pty.send([0x1b, ch as u8]);
                 }
                 _ => match (modifiers, ch) {
+                    (Modifiers::SUPER, 'c') => self.copy_view(),
                     (Modifiers::SUPER, 'v') => self.paste_clipboard(),
                     (Modifiers::SUPER, '-') => self.decrease_font_size(),
                     (Modifiers::SUPER, '=') => self.increase_font_size(),
+                    (Modifiers::SUPER, 'i') => self.show_unsupported = true,
+                    (Modifiers::SUPER, 'd') => {
+                        self.color_scheme.toggle();
+                        self.color_scheme_changed();
+                    }
                     _ => {
                         eprintln!("{:?} (modifiers = {:?})", ch, modifiers);
                         return;
@@ -172,15 +404,186 @@ impl Terminal {
             window::Key::Tab => self.pty.send(b"\t"),
             window::Key::Delete => self.pty.send(b"\x1b[3~"),
 
-            window::Key::ArrowUp => self.pty.send(b"\x1b[A"),
-            window::Key::ArrowDown => self.pty.send(b"\x1b[B"),
-            window::Key::ArrowRight => self.pty.send(b"\x1b[C"),
-            window::Key::ArrowLeft => self.pty.send(b"\x1b[D"),
+            window::Key::ArrowUp
+            | window::Key::ArrowDown
+            | window::Key::ArrowRight
+            | window::Key::ArrowLeft
+            | window::Key::Home
+            | window::Key::End => self.pty.send(self.cursor_key(key)),
+
+            window::Key::PageUp => self.pty.send(b"\x1b[5~"),
+            window::Key::PageDown => self.pty.send(b"\x1b[6~"),
         }
 
         self.dirty = true;
     }
 
+    /// The sequence sent by a cursor key, which depends on `DECCKM`.
+    fn cursor_key(&self, key: window::Key) -> &'static [u8] {
+        let application = self.screen.behaviours.application_cursor;
+
+        match (key, application) {
+            (window::Key::ArrowUp, false) => b"\x1b[A",
+            (window::Key::ArrowDown, false) => b"\x1b[B",
+            (window::Key::ArrowRight, false) => b"\x1b[C",
+            (window::Key::ArrowLeft, false) => b"\x1b[D",
+            (window::Key::Home, false) => b"\x1b[H",
+            (window::Key::End, false) => b"\x1b[F",
+            (window::Key::ArrowUp, true) => b"\x1bOA",
+            (window::Key::ArrowDown, true) => b"\x1bOB",
+            (window::Key::ArrowRight, true) => b"\x1bOC",
+            (window::Key::ArrowLeft, true) => b"\x1bOD",
+            (window::Key::Home, true) => b"\x1bOH",
+            (window::Key::End, true) => b"\x1bOF",
+            _ => b"",
+        }
+    }
+
+    pub fn scroll(&mut self, delta: window::ScrollDelta) {
+        let lines = match delta {
+            window::ScrollDelta::Lines(lines) => lines,
+            window::ScrollDelta::Pixels(pixels) => {
+                let [_, cell_height] = font::cell_size(&self.font_collection.regular);
+                pixels / cell_height
+            }
+        };
+
+        self.scroll_remainder += lines;
+        let whole_lines = self.scroll_remainder.trunc();
+        self.scroll_remainder -= whole_lines;
+
+        // Mouse reporting is not supported, so the wheel is never reported as such. Instead, in the
+        // alternate buffer, programs like `less` are scrolled with the cursor keys.
+        let behaviours = &self.screen.behaviours;
+        if behaviours.alternate_buffer && behaviours.alternate_scroll {
+            let key = if whole_lines > 0.0 {
+                window::Key::ArrowUp
+            } else {
+                window::Key::ArrowDown
+            };
+
+            for _ in 0..whole_lines.abs() as u32 {
+                self.pty.send(self.cursor_key(key));
+            }
+        } else if whole_lines != 0.0 {
+            self.scroll_viewport(viewport::Scroll::Lines(whole_lines as isize));
+        }
+    }
+
+    /// Scroll through the history of the main screen. The alternate screen has no history.
+    fn scroll_viewport(&mut self, scroll: viewport::Scroll) {
+        if self.screen.behaviours.alternate_buffer {
+            return;
+        }
+
+        let grid = &self.screen.grid;
+        let old_offset = self.viewport.offset();
+        self.viewport
+            .scroll(scroll, grid.rows(), grid.history_len());
+        if self.viewport.offset() != old_offset {
+            self.dirty = true;
+        }
+    }
+
+    /// Number of history lines shown above the grid. The alternate screen has no history.
+    fn view_offset(&self) -> usize {
+        if self.screen.behaviours.alternate_buffer {
+            0
+        } else {
+            self.viewport.offset()
+        }
+    }
+
+    /// Rows of the view which changed since the last frame. The rows of the grid are further down
+    /// while the view is scrolled, and every row changes when other lines of the history are
+    /// shown.
+    fn take_view_damage(&mut self, scroll: usize) -> grid::Damage {
+        let grid = &mut self.screen.grid;
+        let history = if scroll == 0 {
+            (0, 0, 0)
+        } else {
+            (scroll, grid.history_pushed(), grid.history_len())
+        };
+
+        let damage = grid.take_damage();
+        if std::mem::replace(&mut self.rendered_history, history) != history {
+            return grid::Damage::All;
+        }
+
+        damage.in_view(scroll, grid.rows())
+    }
+
+    /// Keep the view on the same lines while the shell prints, or go back to the bottom if
+    /// configured to.
+    fn follow_output(&mut self, printed: bool) {
+        if printed && self.config.scrollback.scroll_to_bottom_on_output {
+            self.viewport.scroll(viewport::Scroll::Bottom, 0, 0);
+        }
+
+        let grid = self.screen.main_grid();
+        self.viewport
+            .follow_history(grid.history_pushed(), grid.history_len());
+    }
+
+    /// Keys are not sent to the shell while the unsupported sequences are shown.
+    fn unsupported_key_press(&mut self, key: window::Key, modifiers: window::Modifiers) {
+        use window::Modifiers;
+
+        match (key, modifiers) {
+            (window::Key::Escape, _) | (window::Key::Char('i'), Modifiers::SUPER) => {
+                self.show_unsupported = false;
+                self.unsupported_status = None;
+            }
+            (window::Key::Char('s'), Modifiers::EMPTY) => self.save_unsupported(),
+            _ => return,
+        }
+
+        self.dirty = true;
+    }
+
+    fn save_unsupported(&mut self) {
+        let path =
+            std::env::temp_dir().join(format!("spotty-unsupported-{}.txt", std::process::id()));
+
+        let result = std::fs::File::create(&path).and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            self.screen.unsupported.write_report(&mut writer)?;
+            std::io::Write::flush(&mut writer)
+        });
+
+        self.unsupported_status = Some(match result {
+            Ok(()) => format!("Saved to {}", path.display()),
+            Err(error) => format!("Failed to save to {}: {}", path.display(), error),
+        });
+    }
+
+    /// Text shown while the unsupported sequences are shown.
+    fn unsupported_lines(&self, rows: u16) -> Vec<String> {
+        let mut lines = vec![String::from(
+            "Unsupported sequences (S: save to a file, Esc: close)",
+        )];
+        lines.extend(self.unsupported_status.clone());
+        lines.push(String::new());
+
+        let unsupported = &self.screen.unsupported;
+        if unsupported.is_empty() {
+            lines.push(String::from("None so far."));
+            return lines;
+        }
+
+        let mut report = unsupported.report_lines();
+        let available = usize::from(rows).saturating_sub(lines.len());
+        if report.len() > available {
+            let shown = available.saturating_sub(1);
+            let hidden = report.len() - shown;
+            report.truncate(shown);
+            report.push(format!("… and {} more, save to a file to see all", hidden));
+        }
+
+        lines.extend(report);
+        lines
+    }
+
     fn decrease_font_size(&mut self) {
         self.font_size = f64::max(6.0, self.font_size / 1.25);
         self.reload_font();
@@ -191,28 +594,76 @@ impl Terminal {
         self.reload_font();
     }
 
+    /// Copy the text in view to the clipboard.
+    fn copy_view(&mut self) {
+        let text = self.screen.grid.view_text(self.view_offset());
+        self.window.set_clipboard(&text);
+    }
+
     fn paste_clipboard(&mut self) {
         if let Some(clipboard) = self.window.get_clipboard() {
-            let escaped = clipboard.replace('\x1b', "");
-            let bytes = escaped.into_boxed_str().into_boxed_bytes();
-            if self.screen.behaviours.bracketed_paste {
-                self.pty.send(b"\x1b[200~");
-                self.pty.send(bytes);
-                self.pty.send(b"\x1b[201~");
+            let text = paste::prepare(&clipboard, &self.config.paste);
+
+            // Without bracketed paste every line runs as soon as it is pasted
+            let bracketed = self.screen.behaviours.bracketed_paste;
+            if !bracketed && self.config.paste.confirm_multiline && paste::is_multiline(&text) {
+                self.pending_paste = Some(text);
             } else {
-                self.pty.send(bytes);
+                self.send_paste(&text);
+            }
+        }
+    }
+
+    fn send_paste(&mut self, text: &str) {
+        if self.screen.behaviours.bracketed_paste {
+            self.pty.send(b"\x1b[200~");
+            self.pty.send_chunked(text.as_bytes());
+            self.pty.send(b"\x1b[201~");
+        } else {
+            self.pty.send_chunked(text.as_bytes());
+        }
+    }
+
+    fn confirm_paste_key_press(&mut self, key: window::Key) {
+        match key {
+            window::Key::Enter => {
+                if let Some(text) = self.pending_paste.take() {
+                    self.send_paste(&text);
+                }
             }
+            window::Key::Escape => self.pending_paste = None,
+            _ => return,
         }
+
+        self.dirty = true;
+    }
+
+    /// Text shown while a multiline paste waits for confirmation.
+    fn confirm_paste_lines(&self, text: &str) -> Vec<String> {
+        let preview = text
+            .lines()
+            .flat_map(|line| line.split('\r'))
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        let mut lines = vec![
+            format!("Paste {} lines? (Enter: paste, Esc: cancel)", preview.len()),
+            String::new(),
+        ];
+        lines.extend(preview);
+        lines
     }
 
     pub fn poll_input(&mut self) {
         let start_poll = std::time::Instant::now();
         let max_poll_duration = std::time::Duration::from_millis(10);
+        let mut printed = false;
 
         loop {
             match self.pty.read_timeout(std::time::Duration::from_millis(1)) {
                 Ok(input) => {
                     self.screen.process_input(&input);
+                    printed = true;
                     self.dirty = true;
                 }
                 Err(tty::TryReadError::Empty) => break,
@@ -227,21 +678,155 @@ impl Terminal {
                 break;
             }
         }
+
+        self.follow_output(printed);
+        self.handle_screen_requests();
+
+        // Keep going until the shell has read all of a large paste. The writer thread wakes the
+        // event loop when it can take more of it.
+        self.pty.flush();
+    }
+
+    fn handle_screen_requests(&mut self) {
+        self.sync_pty_grid_size();
+
+        if self.screen.take_bell() {
+            self.ring_bell();
+        }
+        self.update_window_title();
+
+        let responses = self.screen.take_responses();
+        if !responses.is_empty() {
+            self.pty.send(responses.into_boxed_slice());
+        }
+
+        for operation in self.screen.take_window_operations() {
+            if self.config.window_operations.allows(operation) {
+                self.apply_window_operation(operation);
+            } else {
+                debug!(?operation, "window operation denied by config");
+            }
+        }
+    }
+
+    fn apply_window_operation(&mut self, operation: tty::control_code::WindowOperation) {
+        use tty::control_code::WindowOperation;
+
+        match operation {
+            WindowOperation::Deiconify => self.window.set_minimized(false),
+            WindowOperation::Iconify => self.window.set_minimized(true),
+            WindowOperation::Move { x, y } => self.window.set_position(x.into(), y.into()),
+            WindowOperation::ResizePixels { height, width } => {
+                let current = self.window.inner_size();
+                self.window.set_inner_size(window::PhysicalSize::new(
+                    width.map(u32::from).unwrap_or(current.width),
+                    height.map(u32::from).unwrap_or(current.height),
+                ));
+            }
+            WindowOperation::Raise => self.window.raise(),
+            WindowOperation::Lower => self.window.lower(),
+            WindowOperation::ResizeCells { rows, cols } => {
+                let [cell_width, cell_height] = font::cell_size(&self.font_collection.regular);
+                let [current_rows, current_cols] = self.screen.grid.size();
+                let rows = rows.unwrap_or(current_rows);
+                let cols = cols.unwrap_or(current_cols);
+                self.window.set_inner_size(window::PhysicalSize::new(
+                    (f32::from(cols) * cell_width).ceil() as u32,
+                    (f32::from(rows) * cell_height).ceil() as u32,
+                ));
+            }
+            WindowOperation::Maximize => self.window.set_maximized(true),
+            WindowOperation::Unmaximize => self.window.set_maximized(false),
+        }
     }
 
     pub fn render(&mut self) {
         if self.dirty {
             let palette = &crate::color::DEFAULT_PALETTE;
+            let reverse_video = self.screen.behaviours.reverse_video;
 
-            let cursor = self.screen.cursor_render_state(palette);
+            let flash = self.bell.flash(std::time::Instant::now());
 
-            self.renderer.render(render::RenderState {
-                grid: &self.screen.grid,
-                cursor,
-                palette,
-            });
+            let overlay = if let Some(text) = &self.pending_paste {
+                Some(self.confirm_paste_lines(text))
+            } else if self.show_unsupported {
+                Some(self.unsupported_lines(self.screen.grid.rows()))
+            } else {
+                None
+            };
+
+            if let Some(lines) = overlay {
+                let grid = grid::CharacterGrid::from_lines(&lines, self.screen.grid.size());
+                // The screen is drawn from scratch once the overlay goes away
+                self.screen.grid.damage_all();
+
+                self.renderer.render(render::RenderState {
+                    grid: &grid,
+                    damage: grid::Damage::All,
+                    scroll: 0,
+                    cursor: None,
+                    palette,
+                    reverse_video,
+                    images: &self.screen.images,
+                    placements: &[],
+                    flash,
+                });
+            } else {
+                let scroll = self.view_offset();
+                let damage = self.take_view_damage(scroll);
+
+                // The cursor moves down with the grid, and is hidden once it is scrolled out of view
+                let rows = usize::from(self.screen.grid.rows());
+                let cursor = self
+                    .screen
+                    .cursor_render_state(palette)
+                    .and_then(|mut cursor| {
+                        let row = usize::from(cursor.position.row) + scroll;
+                        if row >= rows {
+                            return None;
+                        }
+                        cursor.position.row = row as u16;
+                        Some(cursor)
+                    });
+
+                self.renderer.render(render::RenderState {
+                    grid: &self.screen.grid,
+                    damage,
+                    scroll,
+                    cursor,
+                    palette,
+                    reverse_video,
+                    images: &self.screen.images,
+                    placements: &self.screen.placements,
+                    flash,
+                });
+            }
 
-            self.dirty = false;
+            // Keep drawing until the visual bell has faded out
+            self.dirty = flash.is_some();
+            if self.dirty {
+                self.waker.wake();
+            }
         }
     }
 }
+
+/// Keys which scroll through the history instead of being sent to the shell.
+fn viewport_scroll(key: window::Key, modifiers: window::Modifiers) -> Option<viewport::Scroll> {
+    use viewport::Scroll;
+    use window::{Key, Modifiers};
+
+    let scroll = match (key, modifiers) {
+        (Key::ArrowUp, Modifiers::SHIFT) => Scroll::Lines(1),
+        (Key::ArrowDown, Modifiers::SHIFT) => Scroll::Lines(-1),
+        (Key::PageUp, Modifiers::SHIFT) => Scroll::Pages(1),
+        (Key::PageDown, Modifiers::SHIFT) => Scroll::Pages(-1),
+        (Key::PageUp, Modifiers::SUPER) => Scroll::HalfPages(1),
+        (Key::PageDown, Modifiers::SUPER) => Scroll::HalfPages(-1),
+        (Key::Home, Modifiers::SHIFT) => Scroll::Top,
+        (Key::End, Modifiers::SHIFT) => Scroll::Bottom,
+        _ => return None,
+    };
+
+    Some(scroll)
+}
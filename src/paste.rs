@@ -0,0 +1,85 @@
+//! Cleaning up text from the clipboard before it is sent to the shell.
+
+use crate::config::{ControlCharacters, PasteConfig};
+
+/// Apply the transforms from the config to pasted text.
+pub fn prepare(text: &str, config: &PasteConfig) -> String {
+    let mut text = text;
+    if config.trim_trailing_newline {
+        text = ["\r\n", "\n", "\r"]
+            .iter()
+            .find_map(|newline| text.strip_suffix(newline))
+            .unwrap_or(text);
+    }
+
+    let mut prepared = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\r' | '\n' if config.normalize_newlines => {
+                // Newlines are sent as carriage returns, the same as pressing Return
+                if ch == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                prepared.push('\r');
+            }
+            '\t' | '\r' | '\n' => prepared.push(ch),
+            _ if is_control(ch) => match config.control_characters {
+                ControlCharacters::Strip => {}
+                ControlCharacters::Escape => push_caret_notation(&mut prepared, ch),
+            },
+            _ => prepared.push(ch),
+        }
+    }
+
+    prepared
+}
+
+/// Returns `true` if the text would run more than one command without bracketed paste.
+pub fn is_multiline(text: &str) -> bool {
+    text.contains(['\r', '\n'])
+}
+
+/// C0 and C1 control characters, and DEL.
+fn is_control(ch: char) -> bool {
+    matches!(ch, '\0'..='\x1f' | '\x7f'..='\u{9f}')
+}
+
+/// Show a control character as text: `^[` for ESC, `^?` for DEL and `^[[` for CSI (the 7-bit
+/// equivalent of C1 characters).
+fn push_caret_notation(text: &mut String, ch: char) {
+    let code = ch as u8;
+    match code {
+        0x00..=0x1f => {
+            text.push('^');
+            text.push(char::from(code + 0x40));
+        }
+        0x7f => text.push_str("^?"),
+        _ => {
+            text.push_str("^[");
+            text.push(char::from(code - 0x40));
+        }
+    }
+}
+
+#[test]
+fn prepare_pasted_text() {
+    let mut config = PasteConfig {
+        control_characters: ControlCharacters::Strip,
+        normalize_newlines: true,
+        trim_trailing_newline: true,
+        confirm_multiline: true,
+    };
+
+    let text = "echo \x1b[201~hi\u{9b}\r\nls\tdir\n";
+    assert_eq!(prepare(text, &config), "echo [201~hi\rls\tdir");
+
+    config.control_characters = ControlCharacters::Escape;
+    config.normalize_newlines = false;
+    config.trim_trailing_newline = false;
+    assert_eq!(prepare(text, &config), "echo ^[[201~hi^[[\r\nls\tdir\n");
+
+    assert!(is_multiline("a\rb"));
+    assert!(!is_multiline("a\tb"));
+}
@@ -12,8 +12,30 @@ const FONT_ATLAS_SIZE: usize = 2048;
 
 pub struct RenderState<'a> {
     pub grid: &'a crate::grid::CharacterGrid,
+    /// Rows of the view which changed since the last frame. The others look the same as they
+    /// did, unless the renderer changed how it draws them.
+    pub damage: crate::grid::Damage,
+    /// Number of history lines shown above the grid, see `CharacterGrid::view_row`.
+    pub scroll: usize,
     pub cursor: Option<CursorState>,
     pub palette: &'a crate::color::Palette,
+    /// The default foreground and background colors are swapped (`DECSCNM`).
+    pub reverse_video: bool,
+    pub images: &'a crate::image::ImageStore,
+    pub placements: &'a [crate::image::Placement],
+    /// Opacity of the visual bell drawn over everything, if it is showing.
+    pub flash: Option<f32>,
+}
+
+impl RenderState<'_> {
+    /// The color to draw in place of `color`.
+    pub fn resolve_color(&self, color: crate::color::Color) -> crate::color::Color {
+        if self.reverse_video {
+            crate::color::reverse_video(color)
+        } else {
+            color
+        }
+    }
 }
 
 pub struct CursorState {
@@ -40,17 +62,25 @@ impl Vertex {
         }
     }
 
-    fn glyph_quad(glyph: glyph_cache::Glyph, position: [f32; 2], color: [f32; 4]) -> [Vertex; 6] {
-        let width = glyph.size[0] as f32;
-        let height = glyph.size[1] as f32;
+    /// Quad for a glyph with its baseline at `position`. The glyph is stretched by `scale` and any
+    /// part of it outside the vertical range `clip` (top, bottom) is cut away.
+    fn glyph_quad(
+        glyph: glyph_cache::Glyph,
+        position: [f32; 2],
+        scale: [f32; 2],
+        clip: Option<[f32; 2]>,
+        color: [f32; 4],
+    ) -> [Vertex; 6] {
+        let width = glyph.size[0] as f32 * scale[0];
+        let height = glyph.size[1] as f32 * scale[1];
 
-        let pos_x = position[0] + glyph.metrics.bearing as f32;
-        let pos_y = position[1] - glyph.metrics.ascent as f32;
+        let pos_x = position[0] + glyph.metrics.bearing as f32 * scale[0];
+        let pos_y = position[1] - glyph.metrics.ascent as f32 * scale[1];
 
         let pos_l = pos_x;
         let pos_r = pos_x + width;
-        let pos_t = pos_y;
-        let pos_b = pos_y + height;
+        let mut pos_t = pos_y;
+        let mut pos_b = pos_y + height;
 
         let tex_x = glyph.offset[0] as f32 / FONT_ATLAS_SIZE as f32;
         let tex_y = glyph.offset[1] as f32 / FONT_ATLAS_SIZE as f32;
@@ -59,8 +89,27 @@ impl Vertex {
 
         let tex_l = tex_x;
         let tex_r = tex_x + tex_width;
-        let tex_t = tex_y;
-        let tex_b = tex_y + tex_height;
+        let mut tex_t = tex_y;
+        let mut tex_b = tex_y + tex_height;
+
+        if let Some([clip_t, clip_b]) = clip {
+            if height > 0.0 {
+                let tex_per_pixel = tex_height / height;
+
+                if pos_t < clip_t {
+                    tex_t += (clip_t - pos_t) * tex_per_pixel;
+                    pos_t = clip_t;
+                }
+                if pos_b > clip_b {
+                    tex_b -= (pos_b - clip_b) * tex_per_pixel;
+                    pos_b = clip_b;
+                }
+                if pos_b < pos_t {
+                    pos_b = pos_t;
+                    tex_b = tex_t;
+                }
+            }
+        }
 
         Vertex::quad(
             [pos_l, pos_r, pos_t, pos_b],
@@ -4,6 +4,8 @@ pub struct GlyphCache {
     font: crate::font::FontCollection,
     atlas: super::texture_atlas::TextureAtlas,
     glyphs: HashMap<(char, crate::font::Style), Glyph>,
+    /// Glyphs of grapheme clusters with more than one character.
+    clusters: HashMap<Box<str>, HashMap<crate::font::Style, Glyph>>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -25,6 +27,7 @@ impl GlyphCache {
             font,
             atlas: super::texture_atlas::TextureAtlas::new(atlas_size),
             glyphs: HashMap::new(),
+            clusters: HashMap::new(),
         }
     }
 
@@ -42,9 +45,42 @@ impl GlyphCache {
         style: crate::font::Style,
     ) -> Result<(Glyph, Vec<[u8; 4]>), RasterizationError> {
         let font = self.font.get_with_style(style);
-
         let rasterized = font.rasterize(ch).ok_or(RasterizationError::MissingGlyph)?;
 
+        let (glyph, pixels) = self.store(rasterized)?;
+        self.glyphs.insert((ch, style), glyph);
+
+        Ok((glyph, pixels))
+    }
+
+    pub fn get_cluster(&self, text: &str, style: crate::font::Style) -> Option<Glyph> {
+        self.clusters.get(text)?.get(&style).copied()
+    }
+
+    pub fn rasterize_cluster(
+        &mut self,
+        text: &str,
+        style: crate::font::Style,
+    ) -> Result<(Glyph, Vec<[u8; 4]>), RasterizationError> {
+        let font = self.font.get_with_style(style);
+        let rasterized = font
+            .rasterize_cluster(text)
+            .ok_or(RasterizationError::MissingGlyph)?;
+
+        let (glyph, pixels) = self.store(rasterized)?;
+        self.clusters
+            .entry(text.into())
+            .or_default()
+            .insert(style, glyph);
+
+        Ok((glyph, pixels))
+    }
+
+    /// Reserve space in the atlas for a glyph.
+    fn store(
+        &mut self,
+        rasterized: crate::font::RasterizedGlyph,
+    ) -> Result<(Glyph, Vec<[u8; 4]>), RasterizationError> {
         let offset = self
             .atlas
             .reserve(
@@ -62,8 +98,6 @@ impl GlyphCache {
             metrics: rasterized.metrics,
         };
 
-        self.glyphs.insert((ch, style), glyph);
-
         Ok((glyph, rasterized.bitmap.pixels))
     }
 }
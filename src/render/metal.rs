@@ -17,6 +17,20 @@ pub struct Renderer {
     glyphs: super::glyph_cache::GlyphCache,
     font_atlas: metal::Texture,
     white_texture: metal::Texture,
+
+    image_textures: std::collections::HashMap<crate::image::ImageId, metal::Texture>,
+
+    /// What the grid buffers were last built from, or `None` if they have to be built from
+    /// scratch.
+    built_grid: Option<BuiltGrid>,
+}
+
+struct BuiltGrid {
+    size: [u16; 2],
+    palette: crate::color::Palette,
+    reverse_video: bool,
+    /// Row of the block cursor, whose cells have the colors of the cursor.
+    cursor_row: Option<u16>,
 }
 
 #[repr(C)]
@@ -148,6 +162,10 @@ impl Renderer {
             glyphs: super::glyph_cache::GlyphCache::new(font, super::FONT_ATLAS_SIZE),
             font_atlas,
             white_texture,
+
+            image_textures: std::collections::HashMap::new(),
+
+            built_grid: None,
         }
     }
 
@@ -164,10 +182,13 @@ impl Renderer {
 
     pub fn set_font(&mut self, font: crate::font::FontCollection) {
         self.glyphs = super::glyph_cache::GlyphCache::new(font, super::FONT_ATLAS_SIZE);
+        self.built_grid = None;
     }
 
     pub fn render(&mut self, state: super::RenderState) {
+        self.update_window_uniforms(&state);
         self.update_grid_buffers(&state);
+        self.update_image_textures(&state);
 
         let drawable = self.layer.next_drawable().unwrap();
 
@@ -178,20 +199,55 @@ impl Renderer {
         encoder.set_render_pipeline_state(&self.pipeline);
         encoder.set_fragment_texture(0, Some(&self.font_atlas));
 
+        self.render_images(encoder, &state, |z_index| {
+            z_index < crate::image::BELOW_BACKGROUND
+        });
         self.render_cells(encoder);
+        self.render_images(encoder, &state, |z_index| {
+            (crate::image::BELOW_BACKGROUND..0).contains(&z_index)
+        });
         self.render_characters(encoder);
+        self.render_images(encoder, &state, |z_index| z_index >= 0);
 
         if let Some(cursor) = state.cursor {
             if cursor.style.shape != crate::tty::control_code::CursorShape::Block {
-                self.render_cursor(encoder, cursor, state.palette);
+                let line_size = state.grid.view_row(state.scroll, cursor.position.row).size;
+                self.render_cursor(encoder, cursor, line_size, state.palette);
             }
         }
 
+        if let Some(opacity) = state.flash {
+            self.render_flash(encoder, &state, opacity);
+        }
+
         encoder.end_encoding();
         command_buffer.present_drawable(&drawable);
         command_buffer.commit();
     }
 
+    /// Shrink everything to fit within the window if the grid is larger than it, which happens
+    /// when the number of columns is fixed (`DECCOLM`).
+    fn update_window_uniforms(&mut self, state: &super::RenderState) {
+        let size = self.scaled_window_size(state);
+        self.window_buffer
+            .modify(0..1, |uniforms| uniforms[0].size = size);
+    }
+
+    /// Size of the window in the coordinates used by vertices.
+    fn scaled_window_size(&self, state: &super::RenderState) -> [f32; 2] {
+        let [cell_width, cell_height] = crate::font::cell_size(&self.glyphs.font().regular);
+
+        let window_width = self.size.width as f32;
+        let window_height = self.size.height as f32;
+
+        let grid_width = f32::from(state.grid.cols()) * cell_width;
+        let grid_height = f32::from(state.grid.rows()) * cell_height;
+
+        let scale = f32::max(grid_width / window_width, grid_height / window_height).max(1.0);
+
+        [window_width * scale, window_height * scale]
+    }
+
     fn create_command_encoder<'a>(
         command_buffer: &'a metal::CommandBufferRef,
         target: &metal::TextureRef,
@@ -202,7 +258,9 @@ impl Renderer {
         let attachment = desc.color_attachments().object_at(0).unwrap();
         attachment.set_texture(Some(target));
         attachment.set_clear_color({
-            let [r, g, b, a] = crate::color::DEFAULT_BACKGROUND.into_rgba_f64(state.palette);
+            let [r, g, b, a] = state
+                .resolve_color(crate::color::DEFAULT_BACKGROUND)
+                .into_rgba_f64(state.palette);
             metal::MTLClearColor::new(r, g, b, a)
         });
         attachment.set_load_action(metal::MTLLoadAction::Clear);
@@ -239,6 +297,108 @@ impl Renderer {
         );
     }
 
+    fn render_images(
+        &self,
+        encoder: &metal::RenderCommandEncoderRef,
+        state: &super::RenderState,
+        in_layer: impl Fn(i32) -> bool,
+    ) {
+        let [cell_width, cell_height] = crate::font::cell_size(&self.glyphs.font().regular);
+
+        let mut placements = state
+            .placements
+            .iter()
+            .filter(|placement| in_layer(placement.z_index))
+            .collect::<Vec<_>>();
+        placements.sort_by_key(|placement| placement.z_index);
+
+        for placement in placements {
+            let image = match state.images.get(placement.image) {
+                Some(image) => image,
+                None => continue,
+            };
+            let texture = match self.image_textures.get(&placement.image) {
+                Some(texture) => texture,
+                None => continue,
+            };
+
+            let [x, y, width, height] = placement.destination;
+            let left = (f32::from(placement.col) + x) * cell_width;
+            let top = (placement.row as f32 + state.scroll as f32 + y) * cell_height;
+
+            let [source_x, source_y, source_width, source_height] = placement.source;
+            let image_width = image.width as f32;
+            let image_height = image.height as f32;
+
+            let vertices = super::Vertex::quad(
+                [
+                    left,
+                    left + width * cell_width,
+                    top,
+                    top + height * cell_height,
+                ],
+                [
+                    source_x / image_width,
+                    (source_x + source_width) / image_width,
+                    source_y / image_height,
+                    (source_y + source_height) / image_height,
+                ],
+                [1.0; 4],
+            );
+            let vertices = buffer::Buffer::with_data(&vertices, &self.device);
+
+            encoder.set_fragment_texture(0, Some(texture));
+            encoder.set_vertex_buffers(0, &[Some(&vertices), Some(&self.window_buffer)], &[0; 2]);
+            encoder.draw_primitives(metal::MTLPrimitiveType::Triangle, 0, vertices.len() as u64);
+        }
+    }
+
+    /// Upload the pixels of newly placed images, and release those which have been removed.
+    fn update_image_textures(&mut self, state: &super::RenderState) {
+        self.image_textures
+            .retain(|id, _| state.images.contains(*id));
+
+        for placement in state.placements {
+            if self.image_textures.contains_key(&placement.image) {
+                continue;
+            }
+
+            let image = match state.images.get(placement.image) {
+                Some(image) => image,
+                None => continue,
+            };
+
+            let desc = metal::TextureDescriptor::new();
+
+            desc.set_pixel_format(TEXTURE_FORMAT);
+            desc.set_usage(metal::MTLTextureUsage::ShaderRead);
+
+            desc.set_texture_type(metal::MTLTextureType::D2);
+            desc.set_width(image.width as u64);
+            desc.set_height(image.height as u64);
+
+            // Blending expects premultiplied alpha
+            let pixels = image
+                .pixels
+                .iter()
+                .map(|&[r, g, b, a]| {
+                    let premultiply = |channel: u8| (u16::from(channel) * u16::from(a) / 255) as u8;
+                    [premultiply(r), premultiply(g), premultiply(b), a]
+                })
+                .collect::<Vec<_>>();
+
+            let texture = self.device.new_texture(&desc);
+            texture.replace_region(
+                metal::MTLRegion::new_2d(0, 0, image.width as u64, image.height as u64),
+                0,
+                pixels.as_ptr() as *const _,
+                4 * image.width as u64,
+            );
+
+            self.image_textures.insert(placement.image, texture);
+        }
+    }
+
     fn render_font_atlas(&self, encoder: &metal::RenderCommandEncoderRef) {
         let atlas_vertices = self.create_atlas_vertices();
         encoder.set_fragment_texture(0, Some(&self.font_atlas));
@@ -271,9 +431,10 @@ impl Renderer {
         &self,
         encoder: &metal::RenderCommandEncoderRef,
         cursor: super::CursorState,
+        line_size: crate::grid::LineSize,
         palette: &crate::color::Palette,
     ) {
-        let cursor_vertices = self.create_cursor_vertices(cursor, palette);
+        let cursor_vertices = self.create_cursor_vertices(cursor, line_size, palette);
         encoder.set_fragment_texture(0, Some(&self.white_texture));
         encoder.set_vertex_buffers(
             0,
@@ -287,12 +448,53 @@ impl Renderer {
         );
     }
 
+    /// Cover the window in the foreground color, for the visual bell.
+    fn render_flash(
+        &self,
+        encoder: &metal::RenderCommandEncoderRef,
+        state: &super::RenderState,
+        opacity: f32,
+    ) {
+        const MAX_OPACITY: f32 = 0.3;
+
+        let [width, height] = self.scaled_window_size(state);
+        let [r, g, b, _] = state
+            .resolve_color(crate::color::DEFAULT_FOREGROUND)
+            .into_rgba_f32(state.palette);
+
+        // Blending expects premultiplied alpha
+        let alpha = opacity * MAX_OPACITY;
+        let vertices = super::Vertex::quad(
+            [0.0, width, height, 0.0],
+            [0.0, 1.0, 0.0, 1.0],
+            [r * alpha, g * alpha, b * alpha, alpha],
+        );
+        let flash_vertices = buffer::Buffer::with_data(&vertices, &self.device);
+
+        encoder.set_fragment_texture(0, Some(&self.white_texture));
+        encoder.set_vertex_buffers(
+            0,
+            &[Some(&flash_vertices), Some(&self.window_buffer)],
+            &[0; 2],
+        );
+        encoder.draw_primitives(
+            metal::MTLPrimitiveType::Triangle,
+            0,
+            flash_vertices.len() as u64,
+        );
+    }
+
     fn create_cursor_vertices(
         &self,
         cursor: super::CursorState,
+        line_size: crate::grid::LineSize,
         palette: &crate::color::Palette,
     ) -> buffer::Buffer<super::Vertex> {
-        let [cell_width, cell_height] = crate::font::cell_size(&self.glyphs.font().regular);
+        let [mut cell_width, cell_height] = crate::font::cell_size(&self.glyphs.font().regular);
+
+        if line_size.is_double_width() {
+            cell_width *= 2.0;
+        }
 
         let [width, height] = match cursor.style.shape {
             crate::tty::control_code::CursorShape::Block => [cell_width, cell_height],
@@ -314,82 +516,215 @@ impl Renderer {
 
     // TODO: do this in a compute shader instead
     fn update_grid_buffers(&mut self, state: &super::RenderState) {
+        let cols = state.grid.cols() as usize;
+        let rows = state.grid.rows();
+
+        let cursor_row = state
+            .cursor
+            .as_ref()
+            .filter(|cursor| cursor.style.shape == crate::tty::control_code::CursorShape::Block)
+            .map(|cursor| cursor.position.row);
+
+        let built = self.built_grid.replace(BuiltGrid {
+            size: state.grid.size(),
+            palette: *state.palette,
+            reverse_video: state.reverse_video,
+            cursor_row,
+        });
+
+        let rebuild_all = match &built {
+            Some(built) => {
+                state.damage == crate::grid::Damage::All
+                    || built.size != state.grid.size()
+                    || built.palette != *state.palette
+                    || built.reverse_video != state.reverse_video
+            }
+            None => true,
+        };
+
+        if rebuild_all {
+            let mut cell_quads = Vec::with_capacity(cols * rows as usize);
+            let mut character_quads = Vec::with_capacity(cols * rows as usize);
+
+            for row in 0..rows {
+                self.build_row(state, row, &mut cell_quads, &mut character_quads);
+            }
+
+            self.cell_vertices
+                .update(bytemuck::cast_slice(&cell_quads), &self.device);
+            self.character_vertices
+                .update(bytemuck::cast_slice(&character_quads), &self.device);
+            return;
+        }
+
+        // The block cursor changes the colors of the row it is on, so the row it was on before
+        // has to be built again as well
+        let previous_cursor_row = built.and_then(|built| built.cursor_row);
+
+        let mut cell_quads = Vec::with_capacity(cols);
+        let mut character_quads = Vec::with_capacity(cols);
+
+        for row in 0..rows {
+            let under_cursor = Some(row) == cursor_row || Some(row) == previous_cursor_row;
+            if !under_cursor && !state.damage.contains(row) {
+                continue;
+            }
+
+            cell_quads.clear();
+            character_quads.clear();
+            self.build_row(state, row, &mut cell_quads, &mut character_quads);
+
+            let offset = row as usize * cols * 6;
+            self.cell_vertices
+                .write(bytemuck::cast_slice(&cell_quads), offset);
+            self.character_vertices
+                .write(bytemuck::cast_slice(&character_quads), offset);
+        }
+    }
+
+    /// Append the quads for the backgrounds and characters of a row, one of each for every column.
+    fn build_row(
+        &mut self,
+        state: &super::RenderState,
+        row: u16,
+        cell_quads: &mut Vec<[super::Vertex; 6]>,
+        character_quads: &mut Vec<[super::Vertex; 6]>,
+    ) {
+        use crate::grid::LineSize;
         use crate::tty::control_code::CharacterStyles;
 
         let cols = state.grid.cols();
-        let rows = state.grid.rows();
-
-        let mut cell_quads = Vec::with_capacity(cols as usize * rows as usize);
-        let mut character_quads = Vec::with_capacity(cols as usize * rows as usize);
+        let row_start = cell_quads.len();
 
         let font_metrics = *self.glyphs.font().regular.metrics();
         let advance = font_metrics.advance;
         let descent = font_metrics.descent;
         let line_height = font_metrics.line_height;
 
-        for row in 0..rows {
-            for col in 0..cols {
-                let pos = crate::grid::Position::new(row, col);
-                let cell = state.grid[pos];
+        let view_row = state.grid.view_row(state.scroll, row);
+        let line_size = view_row.size;
+        let cols_in_row = state.grid.cols_with_size(line_size);
 
-                let mut background = cell.background;
-                let mut foreground = cell.foreground;
+        let cell_width = if line_size.is_double_width() {
+            2.0 * advance
+        } else {
+            advance
+        };
 
-                if cell.style.contains(CharacterStyles::INVERSE) {
-                    std::mem::swap(&mut foreground, &mut background);
-                }
+        let cell_top = row as f32 * line_height;
+        let cell_bottom = (1 + row) as f32 * line_height;
+
+        // Double height rows show one half of a glyph twice the size of the row, so place the
+        // baseline where it would be if the glyph spanned this row and its neighbour.
+        let (glyph_scale, baseline_y, clip) = match line_size {
+            LineSize::Single => ([1.0, 1.0], cell_bottom - descent.ceil(), None),
+            LineSize::DoubleWidth => ([2.0, 1.0], cell_bottom - descent.ceil(), None),
+            LineSize::DoubleHeightTop => (
+                [2.0, 2.0],
+                cell_bottom + line_height - 2.0 * descent.ceil(),
+                Some([cell_top, cell_bottom]),
+            ),
+            LineSize::DoubleHeightBottom => (
+                [2.0, 2.0],
+                cell_bottom - 2.0 * descent.ceil(),
+                Some([cell_top, cell_bottom]),
+            ),
+        };
 
-                let cell_left = col as f32 * advance;
-                let cell_bottom = (1 + row) as f32 * line_height;
-
-                let baseline_x = cell_left;
-                let baseline_y = cell_bottom - descent.ceil();
-
-                cell_quads.push(super::Vertex::quad(
-                    [
-                        cell_left,
-                        cell_left + advance,
-                        cell_bottom,
-                        cell_bottom - line_height,
-                    ],
-                    [0.0, 0.0, 0.0, 0.0],
-                    background.into_rgba_f32(state.palette),
-                ));
-
-                character_quads.push(super::Vertex::glyph_quad(
-                    self.get_glyph(cell.character, cell.style),
-                    [baseline_x, baseline_y],
-                    foreground.into_rgba_f32(state.palette),
-                ));
+        for col in 0..cols {
+            // Cells past the end of a double width row are hidden, but we still emit (empty)
+            // quads for them so that every cell keeps the same index.
+            if col >= cols_in_row {
+                cell_quads.push(super::Vertex::quad([0.0; 4], [0.0; 4], [0.0; 4]));
+                character_quads.push(super::Vertex::quad([0.0; 4], [0.0; 4], [0.0; 4]));
+                continue;
             }
+
+            // History lines narrower than the grid are padded with empty cells
+            let cell = view_row
+                .cells
+                .get(usize::from(col))
+                .copied()
+                .unwrap_or_default();
+
+            let attributes = state.grid.attributes(&cell);
+            let mut background = attributes.background;
+            let mut foreground = attributes.foreground;
+
+            if attributes.style.contains(CharacterStyles::INVERSE) {
+                std::mem::swap(&mut foreground, &mut background);
+            }
+
+            let cell_left = col as f32 * cell_width;
+
+            let baseline_x = cell_left;
+
+            // The default background is left transparent so that images below it show through
+            let background = if background == crate::color::DEFAULT_BACKGROUND {
+                [0.0; 4]
+            } else {
+                state.resolve_color(background).into_rgba_f32(state.palette)
+            };
+
+            cell_quads.push(super::Vertex::quad(
+                [cell_left, cell_left + cell_width, cell_bottom, cell_top],
+                [0.0, 0.0, 0.0, 0.0],
+                background,
+            ));
+
+            // Placeholders are replaced by part of an image, and the glyphs of wide characters
+            // extend over the spacer which follows them
+            let spacer = cell.width() == crate::grid::CellWidth::Spacer;
+            if spacer || cell.character() == crate::image::kitty::PLACEHOLDER {
+                character_quads.push(super::Vertex::quad([0.0; 4], [0.0; 4], [0.0; 4]));
+                continue;
+            }
+
+            character_quads.push(super::Vertex::glyph_quad(
+                self.get_glyph(
+                    cell.character(),
+                    state.grid.cluster(&cell),
+                    attributes.style,
+                ),
+                [baseline_x, baseline_y],
+                glyph_scale,
+                clip,
+                state.resolve_color(foreground).into_rgba_f32(state.palette),
+            ));
         }
 
         if let Some(cursor) = &state.cursor {
-            if cursor.style.shape == crate::tty::control_code::CursorShape::Block {
-                let index = cursor.position.col as usize
-                    + cursor.position.row as usize * state.grid.cols() as usize;
+            if cursor.style.shape == crate::tty::control_code::CursorShape::Block
+                && cursor.position.row == row
+            {
+                let col = usize::from(cursor.position.col);
 
                 let cell_color = cursor.color.into_rgba_f32(state.palette);
                 let text_color = cursor.text_color.into_rgba_f32(state.palette);
 
-                cell_quads[index]
-                    .iter_mut()
-                    .for_each(|vertex| vertex.color = cell_color);
-                character_quads[index]
-                    .iter_mut()
-                    .for_each(|vertex| vertex.color = text_color);
+                // The cursor covers both cells of a wide character
+                let wide = view_row
+                    .cells
+                    .get(col)
+                    .map_or(false, |cell| cell.width() == crate::grid::CellWidth::Wide);
+                let covered = if wide { col..col + 2 } else { col..col + 1 };
+
+                for index in covered.filter(|&index| index < usize::from(cols)) {
+                    cell_quads[row_start + index]
+                        .iter_mut()
+                        .for_each(|vertex| vertex.color = cell_color);
+                    character_quads[row_start + index]
+                        .iter_mut()
+                        .for_each(|vertex| vertex.color = text_color);
+                }
             }
         }
-
-        self.cell_vertices
-            .update(bytemuck::cast_slice(&cell_quads), &self.device);
-        self.character_vertices
-            .update(bytemuck::cast_slice(&character_quads), &self.device);
     }
 
     fn get_glyph(
         &mut self,
         ch: char,
+        cluster: Option<&str>,
         styles: crate::tty::control_code::CharacterStyles,
     ) -> super::glyph_cache::Glyph {
         use crate::tty::control_code::CharacterStyles;
@@ -402,24 +737,37 @@ impl Renderer {
             crate::font::Style::Regular
         };
 
+        // Clusters missing from every font are shown as their first character
+        if let Some(cluster) = cluster {
+            if let Some(glyph) = self.glyphs.get_cluster(cluster, style) {
+                return glyph;
+            }
+            if let Ok((glyph, pixels)) = self.glyphs.rasterize_cluster(cluster, style) {
+                self.upload_glyph(glyph, &pixels);
+                return glyph;
+            }
+        }
+
         self.glyphs.get(ch, style).unwrap_or_else(|| {
             let (glyph, pixels) = self.glyphs.rasterize(ch, style).unwrap();
-
-            let region = metal::MTLRegion::new_2d(
-                glyph.offset[0] as u64,
-                glyph.offset[1] as u64,
-                glyph.size[0] as u64,
-                glyph.size[1] as u64,
-            );
-
-            self.font_atlas.replace_region(
-                region,
-                0,
-                pixels.as_ptr() as *const _,
-                4 * glyph.size[0] as u64,
-            );
-
+            self.upload_glyph(glyph, &pixels);
             glyph
         })
     }
+
+    fn upload_glyph(&self, glyph: super::glyph_cache::Glyph, pixels: &[[u8; 4]]) {
+        let region = metal::MTLRegion::new_2d(
+            glyph.offset[0] as u64,
+            glyph.offset[1] as u64,
+            glyph.size[0] as u64,
+            glyph.size[1] as u64,
+        );
+
+        self.font_atlas.replace_region(
+            region,
+            0,
+            pixels.as_ptr() as *const _,
+            4 * glyph.size[0] as u64,
+        );
+    }
 }
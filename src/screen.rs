@@ -1,11 +1,27 @@
+mod kitty;
+
+/// Number of columns in 132 column mode (`DECCOLM`) and after leaving it.
+pub const WIDE_COLUMNS: u16 = 132;
+pub const NARROW_COLUMNS: u16 = 80;
+
+/// Maximum number of titles kept on the title stack (`CSI 22 t`), same as xterm.
+const TITLE_STACK_LIMIT: usize = 10;
+
 pub struct Screen {
     pub title: String,
+    /// Window titles and icon names saved by `CSI 22 t`, if they were selected.
+    pub title_stack: Vec<(Option<String>, Option<String>)>,
+    /// Name shown for the window when it is minimized (`OSC 1`).
+    pub icon_name: String,
 
     pub grid: crate::grid::CharacterGrid,
     pub alternate_grid: crate::grid::CharacterGrid,
 
     pub cursor: crate::grid::Position,
-    pub saved_cursor: crate::grid::Position,
+    /// Cursor saved by `DECSC` for the currently active buffer.
+    pub saved_cursor: SavedCursor,
+    /// Cursor saved by `DECSC` for the inactive buffer. Swapped together with the grids.
+    pub alternate_saved_cursor: SavedCursor,
     pub cursor_style: crate::tty::control_code::CursorStyle,
     pub cursor_color: crate::color::Color,
 
@@ -13,27 +29,162 @@ pub struct Screen {
     pub foreground: crate::color::Color,
     pub background: crate::color::Color,
 
+    pub charsets: Charsets,
+
+    /// Pixels of every image on either screen.
+    pub images: crate::image::ImageStore,
+    /// Images placed on the active screen.
+    pub placements: Vec<crate::image::Placement>,
+    /// Images placed on the inactive screen. Swapped together with the grids.
+    pub alternate_placements: Vec<crate::image::Placement>,
+    /// Number of color registers available to sixel images.
+    pub sixel_color_registers: u16,
+    /// State of the kitty graphics protocol.
+    pub kitty: crate::image::kitty::KittyImages,
+
     pub scrolling_region: std::ops::Range<u16>,
 
     pub behaviours: Behaviours,
 
-    /// Output from the shell that hasn't been parsed yet due to needing more bytes.
-    residual_input: Vec<u8>,
+    /// Size of a single cell in physical pixels, used to answer size reports.
+    pub cell_size: [f32; 2],
+
+    /// The screen is copied to the history before it is cleared (`ED 2`).
+    pub save_cleared_screen: bool,
+
+    /// Characters with an ambiguous East Asian Width take up two cells instead of one.
+    pub wide_ambiguous: bool,
+
+    /// Color scheme preferred by the user, used to answer color scheme reports.
+    pub color_scheme: crate::color_scheme::ColorScheme,
+
+    /// Sequences sent by the shell which are not supported, kept for the whole session.
+    pub unsupported: crate::tty::unsupported::UnsupportedSequences,
+
+    /// Keeps output from the shell that hasn't been parsed yet due to needing more bytes.
+    parser: crate::tty::control_code::Parser,
+
+    /// Replies to the shell that have not yet been sent.
+    responses: Vec<u8>,
+
+    /// Window operations requested by the shell that have not yet been handled.
+    window_operations: Vec<crate::tty::control_code::WindowOperation>,
+
+    /// The bell rang since it was last taken.
+    bell: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct Behaviours {
+    /// Cursor keys send application codes (`DECCKM`).
+    pub application_cursor: bool,
     pub show_cursor: bool,
     pub alternate_buffer: bool,
     pub bracketed_paste: bool,
+    /// Cursor addressing is relative to the scrolling region (`DECOM`).
+    pub origin_mode: bool,
+    /// Default foreground and background colors are swapped (`DECSCNM`).
+    pub reverse_video: bool,
+    /// The application may switch between 80 and 132 columns.
+    pub allow_column_mode: bool,
+    /// The grid has `WIDE_COLUMNS` columns regardless of the size of the window (`DECCOLM`).
+    pub column_mode: bool,
+    /// The mouse wheel sends cursor keys while the alternate buffer is shown.
+    pub alternate_scroll: bool,
+    /// Text is split into grapheme clusters, and every cluster is written to a single cell.
+    pub grapheme_clusters: bool,
+    /// Changes to the preferred color scheme are reported to the application.
+    pub color_scheme_updates: bool,
+}
+
+impl Behaviours {
+    /// Whether a behaviour is enabled, or `None` if it is not implemented.
+    pub fn get(&self, behaviour: crate::tty::control_code::Behaviour) -> Option<bool> {
+        use crate::tty::control_code::Behaviour;
+
+        match behaviour {
+            Behaviour::ApplicationCursor => Some(self.application_cursor),
+            Behaviour::ColumnMode => Some(self.column_mode),
+            Behaviour::ReverseVideo => Some(self.reverse_video),
+            Behaviour::OriginMode => Some(self.origin_mode),
+            Behaviour::ShowCursor => Some(self.show_cursor),
+            Behaviour::AllowColumnMode => Some(self.allow_column_mode),
+            Behaviour::AlternateBuffer => Some(self.alternate_buffer),
+            Behaviour::FocusEvents => None,
+            Behaviour::AlternateScroll => Some(self.alternate_scroll),
+            Behaviour::BracketedPaste => Some(self.bracketed_paste),
+            Behaviour::GraphemeClusters => Some(self.grapheme_clusters),
+            Behaviour::ColorSchemeUpdates => Some(self.color_scheme_updates),
+        }
+    }
 }
 
 impl Default for Behaviours {
     fn default() -> Self {
         Behaviours {
+            application_cursor: false,
             show_cursor: true,
             alternate_buffer: false,
             bracketed_paste: false,
+            origin_mode: false,
+            reverse_video: false,
+            allow_column_mode: false,
+            column_mode: false,
+            alternate_scroll: true,
+            grapheme_clusters: false,
+            color_scheme_updates: false,
+        }
+    }
+}
+
+/// The character sets designated to G0 and G1, and which one of them is in use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Charsets {
+    pub g0: crate::tty::control_code::Charset,
+    pub g1: crate::tty::control_code::Charset,
+    pub active: crate::tty::control_code::CharsetSlot,
+}
+
+impl Default for Charsets {
+    fn default() -> Self {
+        Charsets {
+            g0: crate::tty::control_code::Charset::Ascii,
+            g1: crate::tty::control_code::Charset::Ascii,
+            active: crate::tty::control_code::CharsetSlot::G0,
+        }
+    }
+}
+
+impl Charsets {
+    pub fn translate(&self, ch: char) -> char {
+        match self.active {
+            crate::tty::control_code::CharsetSlot::G0 => self.g0.translate(ch),
+            crate::tty::control_code::CharsetSlot::G1 => self.g1.translate(ch),
+        }
+    }
+}
+
+/// Everything saved by `DECSC` (`ESC 7`) and restored by `DECRC` (`ESC 8`).
+#[derive(Debug, Clone)]
+pub struct SavedCursor {
+    /// May be one past the last column if a wrap was pending when the cursor was saved.
+    pub position: crate::grid::Position,
+    pub style: crate::tty::control_code::CharacterStyles,
+    pub foreground: crate::color::Color,
+    pub background: crate::color::Color,
+    pub charsets: Charsets,
+    pub origin_mode: bool,
+}
+
+impl Default for SavedCursor {
+    fn default() -> Self {
+        SavedCursor {
+            position: crate::grid::Position::new(0, 0),
+            style: crate::tty::control_code::CharacterStyles::empty(),
+            foreground: crate::color::DEFAULT_FOREGROUND,
+            background: crate::color::DEFAULT_BACKGROUND,
+            charsets: Charsets::default(),
+            origin_mode: false,
         }
     }
 }
@@ -42,12 +193,15 @@ impl Screen {
     pub fn new(grid_size: [u16; 2]) -> Screen {
         Screen {
             title: String::from("spotty"),
+            title_stack: Vec::new(),
+            icon_name: String::from("spotty"),
 
             grid: crate::grid::CharacterGrid::new(grid_size[0], grid_size[1]),
             alternate_grid: crate::grid::CharacterGrid::new(grid_size[0], grid_size[1]),
 
             cursor: crate::grid::Position::new(0, 0),
-            saved_cursor: crate::grid::Position::new(0, 0),
+            saved_cursor: SavedCursor::default(),
+            alternate_saved_cursor: SavedCursor::default(),
             cursor_style: crate::tty::control_code::CursorStyle::DEFAULT,
             cursor_color: crate::color::DEFAULT_CURSOR,
 
@@ -56,35 +210,113 @@ impl Screen {
 
             background: crate::color::DEFAULT_BACKGROUND,
 
+            charsets: Charsets::default(),
+
+            images: crate::image::ImageStore::new(crate::image::DEFAULT_MEMORY_LIMIT),
+            placements: Vec::new(),
+            alternate_placements: Vec::new(),
+            sixel_color_registers: crate::image::sixel::DEFAULT_COLOR_REGISTERS,
+            kitty: crate::image::kitty::KittyImages::default(),
+
             scrolling_region: 0..grid_size[0],
             behaviours: Behaviours::default(),
-            residual_input: Vec::new(),
+            cell_size: [1.0, 1.0],
+            save_cleared_screen: false,
+            wide_ambiguous: false,
+            color_scheme: crate::color_scheme::ColorScheme::Dark,
+            unsupported: crate::tty::unsupported::UnsupportedSequences::default(),
+            parser: crate::tty::control_code::Parser::default(),
+            responses: Vec::new(),
+            window_operations: Vec::new(),
+            bell: false,
         }
     }
 
+    /// Change the size of both screens. Lines on the main screen are rewrapped, while the
+    /// alternate screen is cut off or padded.
     pub fn resize_grid(&mut self, grid_size: [u16; 2]) {
-        self.grid = crate::grid::CharacterGrid::new(grid_size[0], grid_size[1]);
-        self.alternate_grid = crate::grid::CharacterGrid::new(grid_size[0], grid_size[1]);
+        let [rows, cols] = grid_size;
+
+        if self.behaviours.alternate_buffer {
+            // The cursor of the main screen is the one saved when switching to the alternate
+            // screen (`CSI ? 1049 h`)
+            let main_cursor = self.alternate_saved_cursor.position;
+            self.alternate_saved_cursor.position =
+                self.alternate_grid.reflow(rows, cols, main_cursor);
+            self.grid.resize(rows, cols);
+        } else {
+            self.cursor = self.grid.reflow(rows, cols, self.cursor);
+            self.alternate_grid.resize(rows, cols);
+        }
 
-        self.cursor = crate::grid::Position::new(0, 0);
+        self.cursor.row = self.cursor.row.min(self.grid.max_row());
+        self.cursor.col = self.cursor.col.min(self.grid.cols());
 
         self.scrolling_region = 0..grid_size[0];
+
+        self.placements.clear();
+        self.alternate_placements.clear();
+        self.collect_image_garbage();
     }
 
     pub fn process_input(&mut self, input: &[u8]) {
-        let mut bytes;
+        // Control strings longer than any image that could be stored are dropped
+        let limit = self.images.memory_limit();
 
-        let bytes = if self.residual_input.is_empty() {
-            input
+        // The parser is taken out while it calls back into the screen, which may be reset
+        let mut parser = std::mem::take(&mut self.parser);
+        parser.parse(input, self, limit);
+        self.parser = parser;
+
+        self.flush_placeholder();
+    }
+
+    /// The grid of the main screen, which keeps the history, even while the alternate screen is
+    /// shown.
+    pub fn main_grid(&self) -> &crate::grid::CharacterGrid {
+        if self.behaviours.alternate_buffer {
+            &self.alternate_grid
         } else {
-            bytes = Vec::with_capacity(self.residual_input.len() + input.len());
-            bytes.append(&mut self.residual_input);
-            bytes.extend_from_slice(input);
-            &bytes
-        };
+            &self.grid
+        }
+    }
+
+    pub fn main_grid_mut(&mut self) -> &mut crate::grid::CharacterGrid {
+        if self.behaviours.alternate_buffer {
+            &mut self.alternate_grid
+        } else {
+            &mut self.grid
+        }
+    }
 
-        let residual = crate::tty::control_code::parse(bytes, self);
-        self.residual_input.extend_from_slice(residual);
+    /// Change the preferred color scheme, and tell the application if it asked to be notified.
+    pub fn set_color_scheme(&mut self, color_scheme: crate::color_scheme::ColorScheme) {
+        if color_scheme != self.color_scheme {
+            self.color_scheme = color_scheme;
+            if self.behaviours.color_scheme_updates {
+                self.push_color_scheme_report();
+            }
+        }
+    }
+
+    fn push_color_scheme_report(&mut self) {
+        let report = format!("\x1b[?997;{}n", self.color_scheme.report_value());
+        self.responses.extend_from_slice(report.as_bytes());
+    }
+
+    /// Take the replies that should be sent back to the shell.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
+
+    /// Take the window operations requested by the shell since the last call.
+    pub fn take_window_operations(&mut self) -> Vec<crate::tty::control_code::WindowOperation> {
+        std::mem::take(&mut self.window_operations)
+    }
+
+    /// Returns `true` if the bell rang since the last call.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
     }
 
     pub fn cursor_render_state(
@@ -92,11 +324,18 @@ impl Screen {
         palette: &crate::color::Palette,
     ) -> Option<crate::render::CursorState> {
         if self.behaviours.show_cursor {
+            // The default cursor color follows the foreground when the default colors are swapped
+            let color = if self.behaviours.reverse_video {
+                crate::color::reverse_video(self.cursor_color)
+            } else {
+                self.cursor_color
+            };
+
             Some(crate::render::CursorState {
                 position: self.virtual_cursor(),
                 style: self.cursor_style,
-                color: self.cursor_color,
-                text_color: self.cursor_color.complement(palette),
+                color,
+                text_color: color.complement(palette),
             })
         } else {
             None
@@ -124,13 +363,21 @@ impl crate::tty::control_code::Terminal for Screen {
     fn invalid_control_sequence(&mut self, bytes: &[u8]) {
         let text = String::from_utf8_lossy(bytes);
         warn!(?text, "invalid control sequence");
+        self.unsupported.record_unrecognised(bytes);
     }
 
     fn text(&mut self, text: &str) {
         trace!(?text);
 
+        if self.behaviours.grapheme_clusters {
+            self.insert_clusters(text);
+            return;
+        }
+
         for ch in text.chars() {
-            self.insert_char(ch);
+            if !self.push_placeholder_diacritic(ch) && !self.combine_with_previous_cell(ch) {
+                self.insert_char(ch);
+            }
         }
     }
 
@@ -143,6 +390,59 @@ impl crate::tty::control_code::Terminal for Screen {
 
     fn bell(&mut self) {
         trace!("bell");
+        self.bell = true;
+    }
+
+    fn reset(&mut self) {
+        debug!("reset");
+
+        // Leaving 132 column mode goes back to 80 columns, like `CSI ? 3 l`
+        let leave_column_mode = self.behaviours.column_mode;
+        let mut size = self.grid.size();
+        if leave_column_mode {
+            size[1] = NARROW_COLUMNS;
+        }
+
+        let mut screen = Screen::new(size);
+        screen.cell_size = self.cell_size;
+        screen
+            .grid
+            .set_history_limit(self.main_grid().history_limit());
+        screen.save_cleared_screen = self.save_cleared_screen;
+        screen.wide_ambiguous = self.wide_ambiguous;
+        screen.color_scheme = self.color_scheme;
+        screen.images.set_memory_limit(self.images.memory_limit());
+        screen.responses = std::mem::take(&mut self.responses);
+        screen.window_operations = std::mem::take(&mut self.window_operations);
+        if leave_column_mode {
+            screen
+                .window_operations
+                .push(crate::tty::control_code::WindowOperation::ResizeCells {
+                    rows: None,
+                    cols: Some(NARROW_COLUMNS),
+                });
+        }
+        screen.bell = self.bell;
+        screen.unsupported = std::mem::take(&mut self.unsupported);
+
+        *self = screen;
+    }
+
+    fn soft_reset(&mut self) {
+        debug!("soft_reset");
+
+        self.behaviours.show_cursor = true;
+        self.behaviours.origin_mode = false;
+        self.behaviours.application_cursor = false;
+
+        self.scrolling_region = 0..self.grid.rows();
+
+        self.style = crate::tty::control_code::CharacterStyles::empty();
+        self.foreground = crate::color::DEFAULT_FOREGROUND;
+        self.background = crate::color::DEFAULT_BACKGROUND;
+        self.charsets = Charsets::default();
+
+        self.saved_cursor = SavedCursor::default();
     }
 
     fn tab(&mut self) {
@@ -198,12 +498,19 @@ impl crate::tty::control_code::Terminal for Screen {
             .saturating_add(count)
             .min(self.scrolling_region.end);
 
+        let shift = clear_end - self.cursor.row;
         self.grid
-            .copy_rows(clear_end..self.scrolling_region.end, self.cursor.row);
+            .rotate_rows_up(self.cursor.row..self.scrolling_region.end, shift);
 
         let rows_below = self.scrolling_region.end - clear_end;
         let copy_end = self.cursor.row + rows_below;
-        self.clear_region(copy_end..self.scrolling_region.end, ..)
+        self.clear_region(copy_end..self.scrolling_region.end, ..);
+
+        self.scroll_placements(
+            self.cursor.row..self.scrolling_region.end,
+            -i32::from(shift),
+        );
+        self.split_wrapped_lines(self.cursor.row..self.scrolling_region.end);
     }
 
     fn insert_lines(&mut self, count: u16) {
@@ -215,47 +522,56 @@ impl crate::tty::control_code::Terminal for Screen {
             .saturating_add(count)
             .min(self.scrolling_region.end);
 
-        let rows_below = self.scrolling_region.end - clear_end;
-        let copy_end = self.cursor.row + rows_below;
-        self.grid.copy_rows(self.cursor.row..copy_end, clear_end);
+        let shift = clear_end - self.cursor.row;
+        self.grid
+            .rotate_rows_down(self.cursor.row..self.scrolling_region.end, shift);
 
         self.clear_region(self.cursor.row..clear_end, ..);
+
+        self.scroll_placements(self.cursor.row..self.scrolling_region.end, i32::from(shift));
+        self.split_wrapped_lines(self.cursor.row..self.scrolling_region.end);
     }
 
     fn scroll_down(&mut self, count: u16) {
         debug!(?count, "scroll_down");
 
-        let copy_destination = self.scrolling_region.start.saturating_add(count);
-        let copy_start = self.scrolling_region.start;
-        let copy_end = self.scrolling_region.end.saturating_sub(count);
-
-        if copy_start > copy_end {
+        if count > self.scrolling_region.end - self.scrolling_region.start {
             return;
         }
 
-        self.grid.copy_rows(copy_start..copy_end, copy_destination);
+        self.grid
+            .rotate_rows_down(self.scrolling_region.clone(), count);
 
         let clear_start = self.scrolling_region.start;
-        let clear_end = copy_destination;
+        let clear_end = clear_start + count;
         self.clear_region(clear_start..clear_end, ..);
+
+        self.scroll_placements(self.scrolling_region.clone(), i32::from(count));
+        self.split_wrapped_lines(self.scrolling_region.clone());
     }
 
     fn scroll_up(&mut self, count: u16) {
         debug!(?count, "scroll_up");
 
-        let copy_destination = self.scrolling_region.start;
-        let copy_start = self.scrolling_region.start + count;
-        let copy_end = self.scrolling_region.end;
-
-        if copy_start > copy_end {
+        if count > self.scrolling_region.end - self.scrolling_region.start {
             return;
         }
 
-        self.grid.copy_rows(copy_start..copy_end, copy_destination);
+        // Only rows scrolled off the whole main screen are kept
+        let whole_screen = self.scrolling_region == (0..self.grid.rows());
+        if whole_screen && !self.behaviours.alternate_buffer {
+            self.grid.push_history(..count);
+        }
+
+        self.grid
+            .rotate_rows_up(self.scrolling_region.clone(), count);
 
         let clear_start = self.scrolling_region.end - count;
         let clear_end = self.scrolling_region.end;
         self.clear_region(clear_start..clear_end, ..);
+
+        self.scroll_placements(self.scrolling_region.clone(), -i32::from(count));
+        self.split_wrapped_lines(self.scrolling_region.clone());
     }
 
     fn move_cursor(&mut self, direction: crate::tty::control_code::Direction, steps: u16) {
@@ -278,36 +594,57 @@ impl crate::tty::control_code::Terminal for Screen {
                     .cursor
                     .col
                     .saturating_add(steps)
-                    .min(self.grid.max_col())
+                    .min(self.max_col_in_row(self.cursor.row))
             }
         }
     }
 
     fn set_cursor_pos(&mut self, row: u16, col: u16) {
         debug!(?row, ?col, "set_cursor_pos");
-        self.cursor.row = row.min(self.grid.max_row());
-        self.cursor.col = col.min(self.grid.max_col());
+        self.cursor.row = self.addressable_row(row);
+        self.cursor.col = col.min(self.max_col_in_row(self.cursor.row));
     }
 
     fn set_cursor_row(&mut self, row: u16) {
         debug!(?row, "set_cursor_row");
-        self.cursor.row = row.min(self.grid.max_row());
+        self.cursor.row = self.addressable_row(row);
     }
 
     fn set_cursor_col(&mut self, col: u16) {
         debug!(?col, "set_cursor_col");
-        self.cursor.col = col.min(self.grid.max_col());
+        self.cursor.col = col.min(self.max_col_in_row(self.cursor.row));
     }
 
     fn save_cursor(&mut self) {
         debug!(?self.cursor, "save_cursor");
-        self.saved_cursor = self.cursor;
+        self.saved_cursor = SavedCursor {
+            position: self.cursor,
+            style: self.style,
+            foreground: self.foreground,
+            background: self.background,
+            charsets: self.charsets,
+            origin_mode: self.behaviours.origin_mode,
+        };
     }
 
     fn restore_cursor(&mut self) {
         debug!(?self.saved_cursor, "restore_cursor");
-        self.cursor.row = self.saved_cursor.row.min(self.grid.max_row());
-        self.cursor.col = self.saved_cursor.col.min(self.grid.max_col());
+
+        let saved = self.saved_cursor.clone();
+
+        let pending_wrap = saved.position.col >= self.grid.cols();
+        self.cursor.row = saved.position.row.min(self.grid.max_row());
+        self.cursor.col = if pending_wrap {
+            self.grid.cols()
+        } else {
+            saved.position.col
+        };
+
+        self.style = saved.style;
+        self.foreground = saved.foreground;
+        self.background = saved.background;
+        self.charsets = saved.charsets;
+        self.behaviours.origin_mode = saved.origin_mode;
     }
 
     fn set_cursor_style(&mut self, style: crate::tty::control_code::CursorStyle) {
@@ -324,6 +661,24 @@ impl crate::tty::control_code::Terminal for Screen {
         self.cursor_color = crate::color::DEFAULT_CURSOR;
     }
 
+    fn set_charset(
+        &mut self,
+        slot: crate::tty::control_code::CharsetSlot,
+        charset: crate::tty::control_code::Charset,
+    ) {
+        debug!(?slot, ?charset, "set_charset");
+
+        match slot {
+            crate::tty::control_code::CharsetSlot::G0 => self.charsets.g0 = charset,
+            crate::tty::control_code::CharsetSlot::G1 => self.charsets.g1 = charset,
+        }
+    }
+
+    fn use_charset(&mut self, slot: crate::tty::control_code::CharsetSlot) {
+        debug!(?slot, "use_charset");
+        self.charsets.active = slot;
+    }
+
     fn set_scrolling_region(&mut self, rows: std::ops::Range<u16>) {
         debug!(?rows, "set_scrolling_region");
 
@@ -357,12 +712,25 @@ impl crate::tty::control_code::Terminal for Screen {
                 self.clear_region(..self.cursor.row, ..);
                 self.clear_current_line(..=self.cursor.col);
             }
-            crate::tty::control_code::ClearRegion::All => self.clear_region(.., ..),
+            crate::tty::control_code::ClearRegion::All => {
+                if self.save_cleared_screen && !self.behaviours.alternate_buffer {
+                    // Trailing blank rows are left out, so clearing twice doesn't add blank lines
+                    let rows = (0..self.grid.rows())
+                        .rev()
+                        .find(|&row| !self.grid.is_row_blank(row))
+                        .map_or(0, |row| row + 1);
+                    self.grid.push_history(..rows);
+                }
+                self.clear_region(.., ..);
+                self.placements.clear();
+                self.collect_image_garbage();
+            }
         }
     }
 
     fn clear_scrollback(&mut self) {
-        todo!("buffer command: clear_scrollback")
+        debug!("clear_scrollback");
+        self.main_grid_mut().clear_history();
     }
 
     fn erase(&mut self, count: u16) {
@@ -370,6 +738,23 @@ impl crate::tty::control_code::Terminal for Screen {
         self.clear_current_line(self.cursor.col..self.cursor.col.saturating_add(count));
     }
 
+    fn screen_alignment_test(&mut self) {
+        debug!("screen_alignment_test");
+
+        let cell = crate::grid::GridCell::new('E', crate::grid::AttributesId::DEFAULT);
+        self.grid.fill_region(.., .., cell);
+
+        self.scrolling_region = 0..self.grid.rows();
+        self.cursor = crate::grid::Position::new(0, 0);
+    }
+
+    fn set_line_size(&mut self, size: crate::grid::LineSize) {
+        debug!(?size, "set_line_size");
+
+        self.grid.set_line_size(self.cursor.row, size);
+        self.cursor.col = self.cursor.col.min(self.max_col_in_row(self.cursor.row));
+    }
+
     fn set_character_style(&mut self, style: crate::tty::control_code::CharacterStyles) {
         trace!(?style, "set_character_style");
         self.style.insert(style);
@@ -405,6 +790,216 @@ impl crate::tty::control_code::Terminal for Screen {
         self.title = text.to_owned();
     }
 
+    fn set_icon_name(&mut self, text: &str) {
+        debug!(?text, "set_icon_name");
+        self.icon_name = text.to_owned();
+    }
+
+    fn push_title(&mut self, selection: crate::tty::control_code::TitleSelection) {
+        debug!(?selection, ?self.title, ?self.icon_name, "push_title");
+
+        if self.title_stack.len() >= TITLE_STACK_LIMIT {
+            self.title_stack.remove(0);
+        }
+
+        let title = Some(self.title.clone()).filter(|_| selection.includes_window_title());
+        let icon_name = Some(self.icon_name.clone()).filter(|_| selection.includes_icon_name());
+        self.title_stack.push((title, icon_name));
+    }
+
+    fn pop_title(&mut self, selection: crate::tty::control_code::TitleSelection) {
+        debug!(?selection, "pop_title");
+
+        let (title, icon_name) = match self.title_stack.pop() {
+            Some(saved) => saved,
+            None => return,
+        };
+
+        if let Some(title) = title.filter(|_| selection.includes_window_title()) {
+            self.title = title;
+        }
+        if let Some(icon_name) = icon_name.filter(|_| selection.includes_icon_name()) {
+            self.icon_name = icon_name;
+        }
+    }
+
+    fn report_size(&mut self, report: crate::tty::control_code::SizeReport) {
+        debug!(?report, "report_size");
+
+        use crate::tty::control_code::SizeReport;
+        use std::io::Write;
+
+        let [cell_width, cell_height] = self.cell_size;
+
+        let _ = match report {
+            SizeReport::TextAreaPixels => {
+                let width = (f32::from(self.grid.cols()) * cell_width) as u32;
+                let height = (f32::from(self.grid.rows()) * cell_height) as u32;
+                write!(self.responses, "\x1b[4;{};{}t", height, width)
+            }
+            SizeReport::CellPixels => {
+                let width = cell_width as u32;
+                let height = cell_height as u32;
+                write!(self.responses, "\x1b[6;{};{}t", height, width)
+            }
+            SizeReport::TextAreaCells => {
+                let [rows, cols] = self.grid.size();
+                write!(self.responses, "\x1b[8;{};{}t", rows, cols)
+            }
+        };
+    }
+
+    fn window_operation(&mut self, operation: crate::tty::control_code::WindowOperation) {
+        debug!(?operation, "window_operation");
+        self.window_operations.push(operation);
+    }
+
+    fn report_device_attributes(&mut self) {
+        debug!("report_device_attributes");
+
+        // VT220 with sixel graphics and ANSI colors
+        self.responses.extend_from_slice(b"\x1b[?62;4;22c");
+    }
+
+    fn report_color_scheme(&mut self) {
+        debug!("report_color_scheme");
+        self.push_color_scheme_report();
+    }
+
+    fn report_mode(&mut self, mode: u16) {
+        debug!(mode, "report_mode");
+
+        use crate::tty::control_code::Behaviour;
+        use std::convert::TryFrom;
+
+        let enabled = match Behaviour::try_from(mode) {
+            Ok(behaviour) => self.behaviours.get(behaviour),
+            Err(()) if mode == 1047 || mode == 1049 => Some(self.behaviours.alternate_buffer),
+            Err(()) => None,
+        };
+
+        let state = match enabled {
+            None => 0,
+            Some(true) => 1,
+            Some(false) => 2,
+        };
+
+        let report = format!("\x1b[?{};{}$y", mode, state);
+        self.responses.extend_from_slice(report.as_bytes());
+    }
+
+    fn graphics_attributes(&mut self, item: u16, action: u16, values: [u16; 2]) {
+        debug!(?item, ?action, ?values, "graphics_attributes");
+
+        use crate::image::sixel::{DEFAULT_COLOR_REGISTERS, MAX_COLOR_REGISTERS, MAX_DIMENSION};
+
+        const SUCCESS: u16 = 0;
+        const INVALID_ITEM: u16 = 1;
+        const INVALID_ACTION: u16 = 2;
+        const FAILURE: u16 = 3;
+
+        const READ: u16 = 1;
+        const RESET: u16 = 2;
+        const SET: u16 = 3;
+        const READ_MAXIMUM: u16 = 4;
+
+        let (status, values) = match item {
+            // Number of color registers
+            1 => match action {
+                READ => (SUCCESS, vec![u32::from(self.sixel_color_registers)]),
+                RESET => {
+                    self.sixel_color_registers = DEFAULT_COLOR_REGISTERS;
+                    (SUCCESS, vec![u32::from(self.sixel_color_registers)])
+                }
+                SET => {
+                    self.sixel_color_registers = values[0].clamp(1, MAX_COLOR_REGISTERS);
+                    (SUCCESS, vec![u32::from(self.sixel_color_registers)])
+                }
+                READ_MAXIMUM => (SUCCESS, vec![u32::from(MAX_COLOR_REGISTERS)]),
+                _ => (INVALID_ACTION, vec![0]),
+            },
+
+            // Size of sixel images, which is limited by the text area
+            2 => match action {
+                READ | RESET => {
+                    let [cell_width, cell_height] = self.cell_size;
+                    let width = (f32::from(self.grid.cols()) * cell_width) as u32;
+                    let height = (f32::from(self.grid.rows()) * cell_height) as u32;
+                    (
+                        SUCCESS,
+                        vec![width.min(MAX_DIMENSION), height.min(MAX_DIMENSION)],
+                    )
+                }
+                SET => (FAILURE, vec![0]),
+                READ_MAXIMUM => (SUCCESS, vec![MAX_DIMENSION, MAX_DIMENSION]),
+                _ => (INVALID_ACTION, vec![0]),
+            },
+
+            // ReGIS graphics are not supported
+            _ => (INVALID_ITEM, vec![0]),
+        };
+
+        use std::io::Write;
+
+        let _ = write!(self.responses, "\x1b[?{};{}", item, status);
+        for value in values {
+            let _ = write!(self.responses, ";{}", value);
+        }
+        self.responses.push(b'S');
+    }
+
+    fn sixel_image(&mut self, data: &[u8], transparent_background: bool) {
+        debug!(len = data.len(), ?transparent_background, "sixel_image");
+
+        let registers = self.sixel_color_registers;
+        let limit = self.images.memory_limit();
+        match crate::image::sixel::decode(data, transparent_background, registers, limit) {
+            Some(image) => {
+                let size = image.size_in_cells(self.cell_size);
+                self.place_image(image, size);
+            }
+            None => debug!("empty sixel image"),
+        }
+    }
+
+    fn inline_image(&mut self, file: &[u8]) {
+        debug!(len = file.len(), "inline_image");
+
+        let file = match crate::image::iterm::File::parse(file) {
+            Ok(file) => file,
+            Err(error) => {
+                warn!(%error, "invalid inline image");
+                return;
+            }
+        };
+
+        if !file.inline {
+            debug!("ignoring file download");
+            return;
+        }
+
+        let image = match crate::image::decode(&file.data, self.images.memory_limit()) {
+            Ok(image) => image,
+            Err(error) => {
+                warn!(%error, "failed to decode inline image");
+                return;
+            }
+        };
+
+        let [rows, cols] = self.grid.size();
+        let size = file.size_in_cells(&image, self.cell_size, [f32::from(cols), f32::from(rows)]);
+        self.place_image(image, size);
+    }
+
+    fn kitty_graphics(&mut self, command: &[u8]) {
+        debug!(len = command.len(), "kitty_graphics");
+
+        match crate::image::kitty::Command::parse(command) {
+            Ok(command) => self.kitty_command(command),
+            Err(error) => warn!(%error, "invalid graphics command"),
+        }
+    }
+
     fn toggle_behaviour(
         &mut self,
         behaviour: crate::tty::control_code::Behaviour,
@@ -415,15 +1010,82 @@ impl crate::tty::control_code::Terminal for Screen {
         use crate::tty::control_code::Behaviour;
 
         match behaviour {
+            Behaviour::ApplicationCursor => {
+                self.behaviours.application_cursor = toggle.is_enabled()
+            }
             Behaviour::ShowCursor => self.behaviours.show_cursor = toggle.is_enabled(),
             Behaviour::AlternateBuffer => {
                 if toggle.is_enabled() != self.behaviours.alternate_buffer {
                     self.behaviours.alternate_buffer = toggle.is_enabled();
                     std::mem::swap(&mut self.grid, &mut self.alternate_grid);
+                    std::mem::swap(&mut self.saved_cursor, &mut self.alternate_saved_cursor);
+                    std::mem::swap(&mut self.placements, &mut self.alternate_placements);
+                    self.grid.damage_all();
                 }
             }
             Behaviour::BracketedPaste => self.behaviours.bracketed_paste = toggle.is_enabled(),
-            _ => warn!(?behaviour, ?toggle, "unimplemented behaviour"),
+            Behaviour::ColorSchemeUpdates => {
+                self.behaviours.color_scheme_updates = toggle.is_enabled()
+            }
+            Behaviour::GraphemeClusters => self.behaviours.grapheme_clusters = toggle.is_enabled(),
+            Behaviour::OriginMode => {
+                self.behaviours.origin_mode = toggle.is_enabled();
+                self.set_cursor_pos(0, 0);
+            }
+            Behaviour::AlternateScroll => self.behaviours.alternate_scroll = toggle.is_enabled(),
+            Behaviour::ReverseVideo => self.behaviours.reverse_video = toggle.is_enabled(),
+            Behaviour::AllowColumnMode => self.behaviours.allow_column_mode = toggle.is_enabled(),
+            Behaviour::ColumnMode => {
+                if !self.behaviours.allow_column_mode {
+                    debug!("column mode is not allowed");
+                    return;
+                }
+
+                let cols = if toggle.is_enabled() {
+                    WIDE_COLUMNS
+                } else {
+                    NARROW_COLUMNS
+                };
+
+                self.behaviours.column_mode = toggle.is_enabled();
+
+                // The hidden screen is resized as usual, but the visible one is cleared instead of
+                // rewrapped, even if the number of columns stays the same. Its lines are not kept in
+                // the history.
+                let rows = self.grid.rows();
+                if self.behaviours.alternate_buffer {
+                    let main_cursor = self.alternate_saved_cursor.position;
+                    self.alternate_saved_cursor.position =
+                        self.alternate_grid.reflow(rows, cols, main_cursor);
+                } else {
+                    self.alternate_grid.resize(rows, cols);
+                }
+                self.grid.resize(rows, cols);
+                self.clear_region(.., ..);
+
+                self.scrolling_region = 0..rows;
+                self.cursor = crate::grid::Position::new(0, 0);
+
+                self.placements.clear();
+                self.alternate_placements.clear();
+                self.collect_image_garbage();
+
+                self.window_operations.push(
+                    crate::tty::control_code::WindowOperation::ResizeCells {
+                        rows: None,
+                        cols: Some(cols),
+                    },
+                );
+            }
+            _ => {
+                warn!(?behaviour, ?toggle, "unimplemented behaviour");
+
+                let terminator = if toggle.is_enabled() { 'h' } else { 'l' };
+                let sequence = format!("\x1b[?{}{}", behaviour as u16, terminator);
+                let name = format!("{:?}", behaviour);
+                self.unsupported
+                    .record_unimplemented(sequence.as_bytes(), &name);
+            }
         }
     }
 }
@@ -447,19 +1109,340 @@ impl Screen {
         }
     }
 
+    /// Translate a row given by the application into a row on the grid, taking origin mode into
+    /// account.
+    fn addressable_row(&self, row: u16) -> u16 {
+        if self.behaviours.origin_mode {
+            let region_end = self
+                .scrolling_region
+                .end
+                .max(self.scrolling_region.start + 1);
+            self.scrolling_region
+                .start
+                .saturating_add(row)
+                .min(region_end - 1)
+                .min(self.grid.max_row())
+        } else {
+            row.min(self.grid.max_row())
+        }
+    }
+
+    fn max_col_in_row(&self, row: u16) -> u16 {
+        self.grid.cols_in_row(row) - 1
+    }
+
     fn insert_char(&mut self, ch: char) {
-        if self.cursor.col == self.grid.cols() {
+        let ch = self.charsets.translate(ch);
+        let wide = crate::grid::is_wide(ch, self.wide_ambiguous);
+        self.insert_cell(ch, None, wide);
+    }
+
+    /// Write text one grapheme cluster per cell (mode 2027).
+    fn insert_clusters(&mut self, text: &str) {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        let text = self.extend_previous_cluster(text);
+
+        for cluster in text.graphemes(true) {
+            let mut chars = cluster.chars();
+            let first = match chars.next() {
+                Some(first) => first,
+                None => continue,
+            };
+
+            // Diacritics of placeholders are not part of the cell
+            let placeholder = first == crate::image::kitty::PLACEHOLDER;
+            if placeholder {
+                self.insert_char(first);
+            }
+            if placeholder || self.push_placeholder_diacritic(first) {
+                for ch in chars {
+                    if !self.push_placeholder_diacritic(ch) {
+                        self.insert_char(ch);
+                    }
+                }
+                continue;
+            }
+
+            self.insert_cluster(cluster);
+        }
+    }
+
+    /// Add the start of the text to the cluster before the cursor if it continues it, like a
+    /// combining mark or an emoji after a zero width joiner. Returns the rest of the text.
+    fn extend_previous_cluster<'a>(&mut self, text: &'a str) -> &'a str {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        let position = match self.previous_cell() {
+            Some(position) => position,
+            None => return text,
+        };
+
+        let mut combined = String::new();
+        self.grid
+            .push_cell_text(&self.grid[position], &mut combined);
+        let previous_len = combined.len();
+        combined.push_str(text);
+
+        let cluster = combined.graphemes(true).next().unwrap_or_default();
+        if cluster.len() <= previous_len {
+            return text;
+        }
+
+        self.set_previous_cluster(position, cluster);
+        &text[cluster.len() - previous_len..]
+    }
+
+    /// Add a character which doesn't take up a cell of its own to the cell before the cursor:
+    /// combining marks, variation selectors, emoji modifiers and anything after a zero width
+    /// joiner. Returns `false` if the character needs a cell of its own.
+    fn combine_with_previous_cell(&mut self, ch: char) -> bool {
+        const ZERO_WIDTH_JOINER: char = '\u{200D}';
+        const EMOJI_MODIFIERS: std::ops::RangeInclusive<char> = '\u{1F3FB}'..='\u{1F3FF}';
+
+        let position = match self.previous_cell() {
+            Some(position) => position,
+            None => return false,
+        };
+
+        let mut cluster = String::new();
+        self.grid.push_cell_text(&self.grid[position], &mut cluster);
+
+        let joined = cluster.ends_with(ZERO_WIDTH_JOINER);
+        if !joined && !EMOJI_MODIFIERS.contains(&ch) && !crate::grid::is_zero_width(ch) {
+            return false;
+        }
+
+        cluster.push(ch);
+        self.set_previous_cluster(position, &cluster);
+        true
+    }
+
+    /// The cell before the cursor, which combining characters are added to. `None` at the start
+    /// of a row or while kitty placeholders are written.
+    fn previous_cell(&self) -> Option<crate::grid::Position> {
+        if self.cursor.col == 0 || self.kitty.placeholder.is_some() {
+            return None;
+        }
+
+        // The cursor is past the end of the row while a wrap is pending
+        let row = self.cursor.row;
+        let col = (self.cursor.col - 1).min(self.grid.cols_in_row(row) - 1);
+        let mut position = crate::grid::Position::new(row, col);
+
+        // The cell before the cursor may be the right half of a wide character
+        if self.grid[position].width() == crate::grid::CellWidth::Spacer && position.col > 0 {
+            let previous = crate::grid::Position::new(row, position.col - 1);
+            if self.grid[previous].width() == crate::grid::CellWidth::Wide {
+                position = previous;
+            }
+        }
+
+        if self.grid[position].character() == crate::image::kitty::PLACEHOLDER {
+            return None;
+        }
+
+        Some(position)
+    }
+
+    /// Replace the grapheme cluster of the cell before the cursor. If the cluster became wide,
+    /// such as a heart followed by `VS16`, it takes up the next cell as well when there is room.
+    fn set_previous_cluster(&mut self, position: crate::grid::Position, cluster: &str) {
+        use crate::grid::CellWidth;
+
+        if let Some(id) = self.grid.intern_cluster(cluster) {
+            self.grid[position].set_cluster(Some(id));
+        }
+
+        let cell = self.grid[position];
+        let spacer_col = position.col + 1;
+        let fits = spacer_col < self.grid.cols_in_row(position.row);
+        let widened = cell.width() == CellWidth::Single
+            && crate::grid::is_cluster_wide(cluster, self.wide_ambiguous);
+        if !widened || !fits || self.cursor.col != spacer_col {
+            return;
+        }
+
+        self.grid
+            .clear_split_wide_characters(position.row, spacer_col..spacer_col + 1);
+        self.grid[position].set_width(CellWidth::Wide);
+
+        let mut spacer = cell;
+        spacer.clear_text();
+        spacer.set_width(CellWidth::Spacer);
+        self.grid[crate::grid::Position::new(position.row, spacer_col)] = spacer;
+
+        self.cursor.col += 1;
+        if self.cursor.col >= self.grid.cols_in_row(self.cursor.row) {
+            self.cursor.col = self.grid.cols();
+        }
+    }
+
+    fn insert_cluster(&mut self, cluster: &str) {
+        let mut chars = cluster.chars();
+        let first = match chars.next() {
+            Some(first) => first,
+            None => return,
+        };
+
+        let translated = self.charsets.translate(first);
+        let translated_cluster;
+        let cluster = if translated == first {
+            cluster
+        } else {
+            translated_cluster = format!("{}{}", translated, chars.as_str());
+            &translated_cluster
+        };
+
+        let id = self.grid.intern_cluster(cluster);
+        let wide = crate::grid::is_cluster_wide(cluster, self.wide_ambiguous);
+        self.insert_cell(translated, id, wide);
+    }
+
+    /// Write a character at the cursor and move the cursor past it. Wide characters take up two
+    /// cells, and wrap early if only one cell is left on the row.
+    fn insert_cell(&mut self, ch: char, cluster: Option<crate::grid::ClusterId>, wide: bool) {
+        use crate::grid::CellWidth;
+
+        self.flush_placeholder();
+
+        // Rows with a single column can only fit half of a wide character
+        let wide = wide && self.grid.cols_in_row(self.cursor.row) > 1;
+        let width = if wide { 2 } else { 1 };
+
+        let cols_in_row = self.grid.cols_in_row(self.cursor.row);
+        if self.cursor.col + width > cols_in_row {
+            // Leave a spacer in the last column if a wide character doesn't fit
+            if self.cursor.col < cols_in_row {
+                let position = self.cursor;
+                self.grid
+                    .clear_split_wide_characters(position.row, position.col..cols_in_row);
+                let mut spacer = self.empty_cell();
+                spacer.set_width(CellWidth::Spacer);
+                self.grid[position] = spacer;
+            }
+
+            self.grid.set_wrapped(self.cursor.row, true);
             self.cursor.col = 0;
             self.advance_row();
         }
 
-        self.grid[self.cursor] = crate::grid::GridCell {
-            character: ch,
+        let position = self.cursor;
+        let columns = position.col..position.col + width;
+        self.remove_placeholder_images(position.row..position.row + 1, columns.clone());
+        self.grid.clear_split_wide_characters(position.row, columns);
+        if ch == crate::image::kitty::PLACEHOLDER {
+            self.begin_placeholder();
+        }
+
+        let attributes = self.grid.intern_attributes(crate::grid::Attributes {
             foreground: self.foreground,
             background: self.background,
             style: self.style,
+        });
+        let mut cell = crate::grid::GridCell::new(ch, attributes);
+        cell.set_cluster(cluster);
+        if wide {
+            cell.set_width(CellWidth::Wide);
+        }
+        self.grid[position] = cell;
+
+        if wide {
+            let mut spacer = crate::grid::GridCell::new(' ', attributes);
+            spacer.set_width(CellWidth::Spacer);
+            self.grid[crate::grid::Position::new(position.row, position.col + 1)] = spacer;
+        }
+
+        self.cursor.col += width;
+
+        // Double width rows wrap halfway through the grid
+        if self.cursor.col >= self.grid.cols_in_row(self.cursor.row) {
+            self.cursor.col = self.grid.cols();
+        }
+    }
+
+    /// Place an image scaled to `size` cells with its top-left corner at the cursor, clearing the
+    /// cells beneath it, and move the cursor to the row below the image.
+    fn place_image(&mut self, image: crate::image::Image, size: [f32; 2]) {
+        let image_size = [image.width, image.height];
+
+        let id = match self.images.insert_transient(image) {
+            Some(id) => id,
+            None => {
+                warn!("image is larger than the memory limit");
+                return;
+            }
         };
-        self.advance_column();
+
+        if self.cursor.col >= self.grid.cols() {
+            self.cursor.col = 0;
+            self.advance_row();
+        }
+
+        let mut placement = crate::image::Placement::new(id, image_size, size);
+        placement.row = i32::from(self.cursor.row);
+        placement.col = self.cursor.col;
+        self.placements.push(placement);
+
+        // Moving the cursor might scroll the screen, which moves the image with it
+        let columns = placement.col..placement.col.saturating_add(placement.cols);
+        for _ in 0..placement.rows {
+            self.clear_current_line(columns.clone());
+            self.advance_row();
+        }
+
+        self.cursor.col = placement.col;
+        self.collect_image_garbage();
+    }
+
+    /// Move the images anchored within `rows` by `delta` rows, and remove those which move out
+    /// of it.
+    fn scroll_placements(&mut self, rows: std::ops::Range<u16>, delta: i32) {
+        if self.placements.is_empty() {
+            return;
+        }
+
+        let whole_screen = rows.start == 0 && rows.end >= self.grid.rows();
+        let start = i32::from(rows.start);
+        let end = i32::from(rows.end);
+
+        let mut removed_any = false;
+
+        for mut placement in std::mem::take(&mut self.placements) {
+            let anchored = whole_screen || (start <= placement.row && placement.row < end);
+            if anchored {
+                placement.row += delta;
+
+                if placement.end_row() <= start || placement.row >= end {
+                    removed_any = true;
+                    continue;
+                }
+            }
+
+            self.placements.push(placement);
+        }
+
+        if removed_any {
+            self.collect_image_garbage();
+        }
+    }
+
+    fn collect_image_garbage(&mut self) {
+        self.images
+            .collect_garbage(self.placements.iter().chain(&self.alternate_placements));
+    }
+
+    /// Rows were moved within `rows`, so the row above no longer continues onto the first of
+    /// them, and the last of them no longer continues onto the row below.
+    fn split_wrapped_lines(&mut self, rows: std::ops::Range<u16>) {
+        if rows.is_empty() {
+            return;
+        }
+
+        if let Some(above) = rows.start.checked_sub(1) {
+            self.grid.set_wrapped(above, false);
+        }
+        self.grid.set_wrapped(rows.end - 1, false);
     }
 
     fn clear_current_line(&mut self, columns: impl std::ops::RangeBounds<u16>) {
@@ -471,16 +1454,26 @@ impl Screen {
         rows: impl std::ops::RangeBounds<u16>,
         columns: impl std::ops::RangeBounds<u16>,
     ) {
+        let rows = crate::grid::into_exclusive_range(rows, self.grid.rows());
+        let columns = crate::grid::into_exclusive_range(columns, self.grid.cols());
+
+        self.remove_placeholder_images(rows.clone(), columns.clone());
+
+        if columns.start != 0 || columns.end != self.grid.cols() {
+            for row in rows.clone() {
+                self.grid.clear_split_wide_characters(row, columns.clone());
+            }
+        }
+
         let cell = self.empty_cell();
         self.grid.fill_region(rows, columns, cell);
     }
 
-    fn empty_cell(&self) -> crate::grid::GridCell {
-        crate::grid::GridCell {
-            character: ' ',
-            foreground: crate::color::DEFAULT_FOREGROUND,
-            background: crate::color::DEFAULT_BACKGROUND,
+    fn empty_cell(&mut self) -> crate::grid::GridCell {
+        let attributes = self.grid.intern_attributes(crate::grid::Attributes {
             style: self.style,
-        }
+            ..crate::grid::Attributes::default()
+        });
+        crate::grid::GridCell::new(' ', attributes)
     }
 }
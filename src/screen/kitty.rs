@@ -0,0 +1,429 @@
+//! Commands of the kitty graphics protocol and placeholder cells.
+
+use crate::image::kitty::{
+    Action, Command, Error, KittyImage, Placeholder, PlacementKey, VirtualPlacement,
+};
+use crate::image::{cells_covered, Placement};
+
+impl super::Screen {
+    pub(super) fn kitty_command(&mut self, command: Command) {
+        let mut command = match self.kitty.pending.take() {
+            // Later chunks only contain the payload and whether more chunks follow
+            Some(mut pending) => {
+                pending.payload.extend_from_slice(&command.payload);
+                pending.more = command.more;
+                pending
+            }
+            None => command,
+        };
+
+        if command.more {
+            if command.payload.len() > self.images.memory_limit() {
+                let error = Error::new("ENOMEM", "transmission exceeds the memory limit");
+                self.kitty_respond(&command, Err(error));
+            } else {
+                self.kitty.pending = Some(command);
+            }
+            return;
+        }
+
+        if command.id != 0 && command.number != 0 {
+            let error = Error::new("EINVAL", "image id and number are mutually exclusive");
+            self.kitty_respond(&command, Err(error));
+            return;
+        }
+
+        // Only commands that identify the image get a response
+        let respond = command.id != 0 || command.number != 0;
+
+        let result = match command.action {
+            Action::Transmit => self.kitty_transmit(&mut command).map(drop),
+            Action::TransmitAndDisplay => self
+                .kitty_transmit(&mut command)
+                .and_then(|id| self.kitty_place(&command, id)),
+            Action::Place => self
+                .kitty_lookup(&mut command)
+                .and_then(|id| self.kitty_place(&command, id)),
+            Action::Delete => {
+                self.kitty_delete(&command);
+                return;
+            }
+            Action::Query => {
+                crate::image::kitty::load_image(&command, self.images.memory_limit()).map(drop)
+            }
+            Action::Unsupported(action) => Err(Error::new(
+                "EINVAL",
+                format!("unsupported action: {}", char::from(action)),
+            )),
+        };
+
+        if let Err(error) = &result {
+            warn!(%error, "graphics command failed");
+        }
+
+        if respond {
+            self.kitty_respond(&command, result);
+        }
+    }
+
+    fn kitty_respond(&mut self, command: &Command, result: Result<(), Error>) {
+        let message = match result {
+            Ok(()) if command.quiet < 1 => String::from("OK"),
+            Err(error) if command.quiet < 2 => error.to_string(),
+            _ => return,
+        };
+
+        use std::io::Write;
+
+        let _ = write!(self.responses, "\x1b_Gi={}", command.id);
+        if command.number != 0 {
+            let _ = write!(self.responses, ",I={}", command.number);
+        }
+        if command.placement_id != 0 {
+            let _ = write!(self.responses, ",p={}", command.placement_id);
+        }
+        let _ = write!(self.responses, ";{}\x1b\\", message);
+    }
+
+    /// Store the transmitted image, replacing any image with the same id, and return its id.
+    fn kitty_transmit(&mut self, command: &mut Command) -> Result<u32, Error> {
+        if command.id == 0 {
+            command.id = self.kitty.unused_id();
+        }
+
+        let image = crate::image::kitty::load_image(command, self.images.memory_limit())?;
+        let image = self
+            .images
+            .insert(image)
+            .ok_or_else(|| Error::new("ENOMEM", "image exceeds the memory limit"))?;
+
+        let stored = KittyImage {
+            image,
+            number: command.number,
+        };
+        if let Some(previous) = self.kitty.images.insert(command.id, stored) {
+            self.kitty_free(previous.image);
+        }
+
+        Ok(command.id)
+    }
+
+    /// Find the image referred to by a command, filling in the id if it's referred to by number.
+    fn kitty_lookup(&self, command: &mut Command) -> Result<u32, Error> {
+        if command.number != 0 {
+            command.id = self.kitty.id_for_number(command.number).unwrap_or(0);
+        }
+
+        match self.kitty.images.get(&command.id) {
+            Some(stored) if self.images.contains(stored.image) => Ok(command.id),
+            _ => Err(Error::new("ENOENT", "no such image")),
+        }
+    }
+
+    /// Place an image at the cursor, or create a virtual placement for placeholders.
+    fn kitty_place(&mut self, command: &Command, id: u32) -> Result<(), Error> {
+        let stored = self.kitty.images[&id];
+        let image = self
+            .images
+            .get(stored.image)
+            .ok_or_else(|| Error::new("ENOENT", "no such image"))?;
+
+        let [x, y, width, height] = command.source;
+        let x = x.min(image.width);
+        let y = y.min(image.height);
+        let width = match width {
+            0 => image.width - x,
+            width => width.min(image.width - x),
+        };
+        let height = match height {
+            0 => image.height - y,
+            height => height.min(image.height - y),
+        };
+
+        if width == 0 || height == 0 {
+            return Err(Error::new("EINVAL", "source rectangle is empty"));
+        }
+
+        let source = [x as f32, y as f32, width as f32, height as f32];
+        let key = PlacementKey {
+            image: id,
+            placement: command.placement_id,
+        };
+
+        // Size of the image in cells, scaled to the given rows or columns
+        let [cell_width, cell_height] = self.cell_size;
+        let natural = [width as f32 / cell_width, height as f32 / cell_height];
+        let [cols, rows] = match (command.cols, command.rows) {
+            (0, 0) => natural,
+            (cols, 0) => [cols as f32, cols as f32 * natural[1] / natural[0]],
+            (0, rows) => [rows as f32 * natural[0] / natural[1], rows as f32],
+            (cols, rows) => [cols as f32, rows as f32],
+        };
+
+        if command.virtual_placement {
+            let placement = VirtualPlacement {
+                rows: cells_covered(rows),
+                cols: cells_covered(cols),
+                source,
+                z_index: command.z_index,
+            };
+            self.kitty.virtual_placements.insert(key, placement);
+            return Ok(());
+        }
+
+        let offset = [
+            command.cell_offset[0] as f32 / cell_width,
+            command.cell_offset[1] as f32 / cell_height,
+        ];
+
+        if command.placement_id != 0 {
+            self.placements
+                .retain(|placement| placement.kitty != Some(key));
+        }
+
+        if self.cursor.col >= self.grid.cols() {
+            self.cursor.col = 0;
+            self.advance_row();
+        }
+
+        let placement = Placement {
+            image: stored.image,
+            row: i32::from(self.cursor.row),
+            col: self.cursor.col,
+            rows: cells_covered(offset[1] + rows),
+            cols: cells_covered(offset[0] + cols),
+            z_index: command.z_index,
+            source,
+            destination: [offset[0], offset[1], cols, rows],
+            kitty: Some(key),
+            placeholder: false,
+        };
+        self.placements.push(placement);
+
+        // Leave the cursor just after the image, on its last row
+        if command.move_cursor {
+            for _ in 1..placement.rows {
+                self.advance_row();
+            }
+            self.cursor.col = self
+                .cursor
+                .col
+                .saturating_add(placement.cols)
+                .min(self.grid.cols());
+        }
+
+        Ok(())
+    }
+
+    fn kitty_delete(&mut self, command: &Command) {
+        let target = command.delete.to_ascii_lowercase();
+        let free_images = command.delete.is_ascii_uppercase();
+
+        let id = match target {
+            b'n' => self.kitty.id_for_number(command.number).unwrap_or(0),
+            _ => command.id,
+        };
+
+        // Cells are given as 1-based `x` and `y`
+        let [x, y, ..] = command.source;
+        let cell_col = x.saturating_sub(1).min(u32::from(u16::MAX)) as u16;
+        let cell_row = y.saturating_sub(1).min(u32::from(u16::MAX)) as u16;
+        let cursor = self.virtual_cursor();
+
+        let key_matches = |key: PlacementKey| match target {
+            b'a' => true,
+            b'i' | b'n' => {
+                key.image == id
+                    && (command.placement_id == 0 || key.placement == command.placement_id)
+            }
+            b'r' => (x..=y).contains(&key.image),
+            _ => false,
+        };
+
+        let matches = |placement: &Placement| {
+            let key = match placement.kitty {
+                Some(key) => key,
+                None => return false,
+            };
+
+            match target {
+                b'c' => placement.covers(cursor.row, cursor.col),
+                b'p' => placement.covers(cell_row, cell_col),
+                b'q' => {
+                    placement.covers(cell_row, cell_col) && placement.z_index == command.z_index
+                }
+                b'x' => {
+                    placement.col <= cell_col
+                        && cell_col < placement.col.saturating_add(placement.cols)
+                }
+                b'y' => {
+                    placement.row <= i32::from(cell_row)
+                        && i32::from(cell_row) < placement.end_row()
+                }
+                b'z' => placement.z_index == command.z_index,
+                _ => key_matches(key),
+            }
+        };
+
+        let mut deleted = Vec::new();
+        self.placements.retain(|placement| {
+            if matches(placement) {
+                deleted.push(placement.image);
+                false
+            } else {
+                true
+            }
+        });
+        self.kitty
+            .virtual_placements
+            .retain(|key, _| !key_matches(*key));
+
+        if !free_images {
+            return;
+        }
+
+        // Images chosen by id are freed even if they had no placements. Others are freed once
+        // none of their placements remain.
+        let in_use = self
+            .placements
+            .iter()
+            .chain(&self.alternate_placements)
+            .map(|placement| placement.image)
+            .collect::<std::collections::HashSet<_>>();
+
+        let freed = self
+            .kitty
+            .images
+            .iter()
+            .filter(|(&kitty_id, stored)| {
+                let chosen = match target {
+                    b'i' | b'n' => kitty_id == id && command.placement_id == 0,
+                    b'r' => (x..=y).contains(&kitty_id),
+                    _ => false,
+                };
+                chosen || (deleted.contains(&stored.image) && !in_use.contains(&stored.image))
+            })
+            .map(|(&kitty_id, _)| kitty_id)
+            .collect::<Vec<_>>();
+
+        for kitty_id in freed {
+            if let Some(stored) = self.kitty.images.remove(&kitty_id) {
+                self.kitty_free(stored.image);
+            }
+            self.kitty
+                .virtual_placements
+                .retain(|key, _| key.image != kitty_id);
+        }
+    }
+
+    /// Remove an image and every placement of it.
+    fn kitty_free(&mut self, image: crate::image::ImageId) {
+        self.placements.retain(|placement| placement.image != image);
+        self.alternate_placements
+            .retain(|placement| placement.image != image);
+        self.images.remove(image);
+    }
+
+    /// Diacritics after a placeholder give its position within the image. Returns `false` if the
+    /// character is not part of a placeholder.
+    pub(super) fn push_placeholder_diacritic(&mut self, ch: char) -> bool {
+        match &mut self.kitty.placeholder {
+            Some(placeholder) => placeholder.push_diacritic(ch),
+            None => false,
+        }
+    }
+
+    /// Remember a placeholder written at the cursor. It is shown once its diacritics have arrived.
+    pub(super) fn begin_placeholder(&mut self) {
+        self.flush_placeholder();
+
+        let previous = self.kitty.previous_placeholder.as_ref();
+        let placeholder = Placeholder::new(self.cursor, self.foreground, previous);
+        self.kitty.placeholder = Some(placeholder);
+    }
+
+    /// Show the part of the image belonging to the most recent placeholder cell.
+    pub(super) fn flush_placeholder(&mut self) {
+        let placeholder = match self.kitty.placeholder.take() {
+            Some(placeholder) => placeholder,
+            None => return,
+        };
+        self.kitty.previous_placeholder = Some(placeholder);
+
+        let id = placeholder.image_id();
+        let virtual_placement = self
+            .kitty
+            .virtual_placements
+            .iter()
+            .find(|(key, _)| key.image == id)
+            .map(|(key, placement)| (*key, *placement));
+        let (key, virtual_placement) = match virtual_placement {
+            Some(found) => found,
+            None => return,
+        };
+
+        let image = match self.kitty.images.get(&id) {
+            Some(stored) => stored.image,
+            None => return,
+        };
+
+        let cell = virtual_placement.cell(placeholder.row, placeholder.col, self.cell_size);
+        let (source, destination) = match cell {
+            Some(cell) => cell,
+            None => return,
+        };
+
+        let position = placeholder.position;
+
+        // Continue the placement of the cell to the left, so each row of the image is drawn at once
+        if let Some(last) = self.placements.last_mut() {
+            const EPSILON: f32 = 1e-3;
+
+            let continues = last.placeholder
+                && last.kitty == Some(key)
+                && last.row == i32::from(position.row)
+                && last.col.saturating_add(last.cols) == position.col
+                && (last.destination[0] + last.destination[2] - f32::from(last.cols)).abs()
+                    < EPSILON
+                && destination[0].abs() < EPSILON
+                && (last.destination[1] - destination[1]).abs() < EPSILON
+                && (last.destination[3] - destination[3]).abs() < EPSILON
+                && (last.source[0] + last.source[2] - source[0]).abs() < EPSILON;
+
+            if continues {
+                last.cols += 1;
+                last.source[2] += source[2];
+                last.destination[2] += destination[2];
+                return;
+            }
+        }
+
+        self.placements.push(Placement {
+            image,
+            row: i32::from(position.row),
+            col: position.col,
+            rows: 1,
+            cols: 1,
+            z_index: virtual_placement.z_index,
+            source,
+            destination,
+            kitty: Some(key),
+            placeholder: true,
+        });
+    }
+
+    /// Remove the images shown by placeholders within the given cells.
+    pub(super) fn remove_placeholder_images(
+        &mut self,
+        rows: std::ops::Range<u16>,
+        columns: std::ops::Range<u16>,
+    ) {
+        self.placements.retain(|placement| {
+            let overlaps = placement.row < i32::from(rows.end)
+                && placement.end_row() > i32::from(rows.start)
+                && placement.col < columns.end
+                && placement.col.saturating_add(placement.cols) > columns.start;
+            !(placement.placeholder && overlaps)
+        });
+    }
+}
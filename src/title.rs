@@ -0,0 +1,126 @@
+//! Window titles built from a template, such as `{bell}{process} in {cwd} ({cols}x{rows})`.
+//!
+//! Fields are written in braces, and `{{` and `}}` stand for literal braces. The fields are:
+//!
+//! - `{title}`: title set by the application (`OSC 0` or `OSC 2`)
+//! - `{icon}`: icon name set by the application (`OSC 0` or `OSC 1`)
+//! - `{process}`: name of the program in the foreground, such as `ssh`
+//! - `{cwd}`: working directory of that program, with the home directory shown as `~`
+//! - `{cols}` and `{rows}`: size of the grid
+//! - `{bell}`: a marker if the bell rang while the window was not focused
+//!
+//! Unknown fields are kept as they are.
+
+/// Values the fields of a template are replaced with.
+#[derive(Debug, Default)]
+pub struct Fields<'a> {
+    pub title: &'a str,
+    pub icon_name: &'a str,
+    pub process: Option<&'a str>,
+    pub cwd: Option<&'a std::path::Path>,
+    pub cols: u16,
+    pub rows: u16,
+    pub bell: bool,
+}
+
+/// Returns `true` if the template shows the foreground process or its working directory, which
+/// have to be looked up.
+pub fn uses_process(template: &str) -> bool {
+    template.contains("{process}") || template.contains("{cwd}")
+}
+
+pub fn expand(template: &str, fields: &Fields) -> String {
+    let mut title = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(index) = rest.find(['{', '}']) {
+        title.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(stripped) = rest.strip_prefix("{{") {
+            title.push('{');
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix("}}") {
+            title.push('}');
+            rest = stripped;
+        } else if let Some(end) = rest.find('}').filter(|_| rest.starts_with('{')) {
+            let name = &rest[1..end];
+            if !push_field(&mut title, name, fields) {
+                title.push_str(&rest[..=end]);
+            }
+            rest = &rest[end + 1..];
+        } else {
+            title.push_str(&rest[..1]);
+            rest = &rest[1..];
+        }
+    }
+
+    title.push_str(rest);
+    title
+}
+
+/// Append the value of a field. Returns `false` if there is no field with that name.
+fn push_field(title: &mut String, name: &str, fields: &Fields) -> bool {
+    match name {
+        "title" => title.push_str(fields.title),
+        "icon" => title.push_str(fields.icon_name),
+        "process" => title.push_str(fields.process.unwrap_or_default()),
+        "cwd" => {
+            if let Some(cwd) = fields.cwd {
+                title.push_str(&abbreviate_home(cwd));
+            }
+        }
+        "cols" => title.push_str(&fields.cols.to_string()),
+        "rows" => title.push_str(&fields.rows.to_string()),
+        "bell" => {
+            if fields.bell {
+                title.push_str(crate::bell::TITLE_MARKER);
+            }
+        }
+        _ => return false,
+    }
+
+    true
+}
+
+/// Show paths in the home directory starting with `~`.
+fn abbreviate_home(path: &std::path::Path) -> String {
+    let home = std::env::var_os("HOME").map(std::path::PathBuf::from);
+    match home
+        .as_deref()
+        .and_then(|home| path.strip_prefix(home).ok())
+    {
+        Some(relative) if relative.as_os_str().is_empty() => "~".to_owned(),
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
+
+#[test]
+fn expand_title_template() {
+    let fields = Fields {
+        title: "vim",
+        icon_name: "editor",
+        process: Some("ssh"),
+        cwd: Some(std::path::Path::new("/srv/www")),
+        cols: 80,
+        rows: 24,
+        bell: true,
+    };
+
+    assert_eq!(
+        expand("{bell}{process} in {cwd} ({cols}x{rows})", &fields),
+        "🔔 ssh in /srv/www (80x24)"
+    );
+    assert_eq!(
+        expand("{{title}} {title} {icon} {unknown} }", &fields),
+        "{title} vim editor {unknown} }"
+    );
+    assert_eq!(expand("{title", &fields), "{title");
+
+    let fields = Fields {
+        title: "spotty",
+        ..Fields::default()
+    };
+    assert_eq!(expand("{bell}{title}{process}{cwd}", &fields), "spotty");
+}
@@ -1,11 +1,25 @@
+pub mod action;
 pub mod control_code;
+pub mod process;
+pub mod unsupported;
 
 use crate::inline::InlineBytes;
 
+/// Large inputs, such as pastes, are sent to the writer thread in chunks of this many bytes.
+const INPUT_CHUNK_SIZE: usize = 4096;
+
 pub struct Psuedoterminal {
     master_fd: nix::pty::PtyMaster,
     input: flume::Sender<InlineBytes>,
     output: flume::Receiver<InlineBytes>,
+
+    /// Input which did not fit in the channel to the writer thread yet, in order. Keeping it here
+    /// instead of waiting for the shell to read means the UI never blocks on a slow shell.
+    backlog: std::collections::VecDeque<InlineBytes>,
+
+    /// Set while the channel to the writer thread is full, so that it wakes the event loop once
+    /// there is room for the backlog again.
+    backlog_waiting: std::sync::Arc<std::sync::atomic::AtomicBool>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -32,16 +46,34 @@ impl Psuedoterminal {
             ))
         };
 
-        std::thread::spawn(move || Self::handle_terminal_input(receiver, writer).unwrap());
+        let backlog_waiting = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        let input_waker = waker.clone();
+        let input_backlog_waiting = backlog_waiting.clone();
+        std::thread::spawn(move || {
+            Self::handle_terminal_input(receiver, writer, input_backlog_waiting, input_waker)
+                .unwrap()
+        });
         std::thread::spawn(move || Self::handle_terminal_output(sender, reader, waker).unwrap());
 
         Ok(Psuedoterminal {
             master_fd: link.master_fd,
             input,
             output,
+            backlog: std::collections::VecDeque::new(),
+            backlog_waiting,
         })
     }
 
+    /// The process in the foreground of the terminal, such as the shell or the program it is
+    /// running.
+    pub fn foreground_process(&self) -> Option<process::Process> {
+        use std::os::unix::io::AsRawFd;
+
+        let pid = nix::unistd::tcgetpgrp(self.master_fd.as_raw_fd()).ok()?;
+        process::Process::from_pid(pid)
+    }
+
     pub fn set_grid_size(&self, size: [u16; 2]) {
         use std::os::unix::io::AsRawFd;
 
@@ -65,15 +97,53 @@ impl Psuedoterminal {
         })
     }
 
-    pub fn send(&self, bytes: impl Into<InlineBytes>) {
-        let _ = self.input.send(bytes.into());
+    pub fn send(&mut self, bytes: impl Into<InlineBytes>) {
+        self.backlog.push_back(bytes.into());
+        self.flush();
+    }
+
+    /// Send a large amount of input in chunks, so the writer thread can make progress before all
+    /// of it has been queued.
+    pub fn send_chunked(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(INPUT_CHUNK_SIZE) {
+            self.backlog.push_back(InlineBytes::new(chunk));
+        }
+        self.flush();
+    }
+
+    /// Move as much of the backlog to the writer thread as fits. If some input is still waiting,
+    /// the writer thread wakes the event loop when it has made room for it.
+    pub fn flush(&mut self) {
+        use std::sync::atomic::Ordering;
+
+        let mut asked_for_wake = false;
+        while let Some(bytes) = self.backlog.pop_front() {
+            match self.input.try_send(bytes) {
+                Ok(()) => {}
+                Err(flume::TrySendError::Full(bytes)) => {
+                    self.backlog.push_front(bytes);
+                    if asked_for_wake {
+                        return;
+                    }
+
+                    // Try once more after asking, in case the writer thread emptied the channel
+                    // before it could see the request
+                    self.backlog_waiting.store(true, Ordering::SeqCst);
+                    asked_for_wake = true;
+                }
+                Err(flume::TrySendError::Disconnected(_)) => self.backlog.clear(),
+            }
+        }
     }
 
     fn handle_terminal_input(
         receiver: flume::Receiver<InlineBytes>,
         mut writer: std::io::BufWriter<std::fs::File>,
+        backlog_waiting: std::sync::Arc<std::sync::atomic::AtomicBool>,
+        waker: crate::window::EventLoopWaker,
     ) -> std::io::Result<()> {
         use std::io::Write;
+        use std::sync::atomic::Ordering;
 
         let into_try_recv_error = |error| match error {
             flume::RecvError::Disconnected => flume::TryRecvError::Disconnected,
@@ -84,6 +154,11 @@ impl Psuedoterminal {
         loop {
             match next_item {
                 Ok(bytes) => {
+                    // Taking the bytes made room in the channel
+                    if backlog_waiting.swap(false, Ordering::SeqCst) {
+                        waker.wake();
+                    }
+
                     writer.write_all(&bytes)?;
                     next_item = receiver.try_recv();
                 }
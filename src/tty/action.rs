@@ -0,0 +1,475 @@
+//! The output of the parser as plain data, for inspecting what an application sent.
+
+use super::control_code::{
+    Behaviour, CharacterStyles, Charset, CharsetSlot, ClearRegion, CursorStyle, Direction,
+    SizeReport, Terminal, TitleSelection, Toggle, WindowOperation,
+};
+
+/// Bytes which are shown as an escaped string.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Bytes(pub Vec<u8>);
+
+impl std::fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "b\"")?;
+        for &byte in &self.0 {
+            write!(f, "{}", std::ascii::escape_default(byte))?;
+        }
+        write!(f, "\"")
+    }
+}
+
+/// A call to one of the methods of `Terminal`, with the same arguments.
+#[derive(Debug, Clone)]
+pub enum Action {
+    InvalidControlSequence(Bytes),
+    Text(String),
+    InvalidUtf8(Bytes),
+    Bell,
+    Reset,
+    SoftReset,
+
+    Tab,
+    Backspace,
+    CarriageReturn,
+    LineFeed,
+    ReverseLineFeed,
+    DeleteLines(u16),
+    InsertLines(u16),
+    ScrollDown(u16),
+    ScrollUp(u16),
+    MoveCursor(Direction, u16),
+    SetCursorPos {
+        row: u16,
+        col: u16,
+    },
+    SetCursorRow(u16),
+    SetCursorCol(u16),
+    SaveCursor,
+    RestoreCursor,
+    SetCursorStyle(CursorStyle),
+    SetCursorColor(crate::color::Color),
+    ResetCursorColor,
+
+    SetCharset(CharsetSlot, Charset),
+    UseCharset(CharsetSlot),
+
+    SetScrollingRegion(std::ops::Range<u16>),
+
+    ClearLine(ClearRegion),
+    ClearScreen(ClearRegion),
+    ClearScrollback,
+    Erase(u16),
+    ScreenAlignmentTest,
+
+    SetLineSize(crate::grid::LineSize),
+
+    SetCharacterStyle(CharacterStyles),
+    ResetCharacterStyle(CharacterStyles),
+
+    SetForegroundColor(crate::color::Color),
+    ResetForegroundColor,
+    SetBackgroundColor(crate::color::Color),
+    ResetBackgroundColor,
+
+    SetWindowTitle(String),
+    SetIconName(String),
+    PushTitle(TitleSelection),
+    PopTitle(TitleSelection),
+
+    ReportSize(SizeReport),
+    WindowOperation(WindowOperation),
+
+    ReportDeviceAttributes,
+    ReportColorScheme,
+    ReportMode(u16),
+    GraphicsAttributes {
+        item: u16,
+        action: u16,
+        values: [u16; 2],
+    },
+
+    SixelImage {
+        data: Bytes,
+        transparent_background: bool,
+    },
+    KittyGraphics(Bytes),
+    InlineImage(Bytes),
+
+    ToggleBehaviour(Behaviour, Toggle),
+}
+
+/// Implements `Terminal` by collecting every call as an `Action`.
+#[derive(Debug, Default)]
+pub struct ActionCollector {
+    /// Every action, together with the offset of the bytes it was parsed from.
+    pub actions: Vec<(usize, Action)>,
+    offset: usize,
+}
+
+impl ActionCollector {
+    /// Parse `bytes` and collect the actions they contain. Returns the bytes at the end which do
+    /// not form a complete sequence.
+    pub fn parse<'a>(&mut self, bytes: &'a [u8]) -> &'a [u8] {
+        super::control_code::parse_with_offsets(bytes, self, |collector, offset| {
+            collector.offset = offset
+        })
+    }
+
+    fn push(&mut self, action: Action) {
+        self.actions.push((self.offset, action));
+    }
+}
+
+impl Terminal for ActionCollector {
+    fn invalid_control_sequence(&mut self, bytes: &[u8]) {
+        self.push(Action::InvalidControlSequence(Bytes(bytes.to_vec())));
+    }
+
+    fn text(&mut self, text: &str) {
+        self.push(Action::Text(text.to_owned()));
+    }
+
+    fn invalid_utf8(&mut self, text: &[u8]) {
+        self.push(Action::InvalidUtf8(Bytes(text.to_vec())));
+    }
+
+    fn bell(&mut self) {
+        self.push(Action::Bell);
+    }
+
+    fn reset(&mut self) {
+        self.push(Action::Reset);
+    }
+
+    fn soft_reset(&mut self) {
+        self.push(Action::SoftReset);
+    }
+
+    fn tab(&mut self) {
+        self.push(Action::Tab);
+    }
+
+    fn backspace(&mut self) {
+        self.push(Action::Backspace);
+    }
+
+    fn carriage_return(&mut self) {
+        self.push(Action::CarriageReturn);
+    }
+
+    fn line_feed(&mut self) {
+        self.push(Action::LineFeed);
+    }
+
+    fn reverse_line_feed(&mut self) {
+        self.push(Action::ReverseLineFeed);
+    }
+
+    fn delete_lines(&mut self, count: u16) {
+        self.push(Action::DeleteLines(count));
+    }
+
+    fn insert_lines(&mut self, count: u16) {
+        self.push(Action::InsertLines(count));
+    }
+
+    fn scroll_down(&mut self, count: u16) {
+        self.push(Action::ScrollDown(count));
+    }
+
+    fn scroll_up(&mut self, count: u16) {
+        self.push(Action::ScrollUp(count));
+    }
+
+    fn move_cursor(&mut self, direction: Direction, steps: u16) {
+        self.push(Action::MoveCursor(direction, steps));
+    }
+
+    fn set_cursor_pos(&mut self, row: u16, col: u16) {
+        self.push(Action::SetCursorPos { row, col });
+    }
+
+    fn set_cursor_row(&mut self, row: u16) {
+        self.push(Action::SetCursorRow(row));
+    }
+
+    fn set_cursor_col(&mut self, col: u16) {
+        self.push(Action::SetCursorCol(col));
+    }
+
+    fn save_cursor(&mut self) {
+        self.push(Action::SaveCursor);
+    }
+
+    fn restore_cursor(&mut self) {
+        self.push(Action::RestoreCursor);
+    }
+
+    fn set_cursor_style(&mut self, style: CursorStyle) {
+        self.push(Action::SetCursorStyle(style));
+    }
+
+    fn set_cursor_color(&mut self, color: crate::color::Color) {
+        self.push(Action::SetCursorColor(color));
+    }
+
+    fn reset_cursor_color(&mut self) {
+        self.push(Action::ResetCursorColor);
+    }
+
+    fn set_charset(&mut self, slot: CharsetSlot, charset: Charset) {
+        self.push(Action::SetCharset(slot, charset));
+    }
+
+    fn use_charset(&mut self, slot: CharsetSlot) {
+        self.push(Action::UseCharset(slot));
+    }
+
+    fn set_scrolling_region(&mut self, rows: std::ops::Range<u16>) {
+        self.push(Action::SetScrollingRegion(rows));
+    }
+
+    fn clear_line(&mut self, region: ClearRegion) {
+        self.push(Action::ClearLine(region));
+    }
+
+    fn clear_screen(&mut self, region: ClearRegion) {
+        self.push(Action::ClearScreen(region));
+    }
+
+    fn clear_scrollback(&mut self) {
+        self.push(Action::ClearScrollback);
+    }
+
+    fn erase(&mut self, count: u16) {
+        self.push(Action::Erase(count));
+    }
+
+    fn screen_alignment_test(&mut self) {
+        self.push(Action::ScreenAlignmentTest);
+    }
+
+    fn set_line_size(&mut self, size: crate::grid::LineSize) {
+        self.push(Action::SetLineSize(size));
+    }
+
+    fn set_character_style(&mut self, style: CharacterStyles) {
+        self.push(Action::SetCharacterStyle(style));
+    }
+
+    fn reset_character_style(&mut self, style: CharacterStyles) {
+        self.push(Action::ResetCharacterStyle(style));
+    }
+
+    fn set_foreground_color(&mut self, color: crate::color::Color) {
+        self.push(Action::SetForegroundColor(color));
+    }
+
+    fn reset_foreground_color(&mut self) {
+        self.push(Action::ResetForegroundColor);
+    }
+
+    fn set_background_color(&mut self, color: crate::color::Color) {
+        self.push(Action::SetBackgroundColor(color));
+    }
+
+    fn reset_background_color(&mut self) {
+        self.push(Action::ResetBackgroundColor);
+    }
+
+    fn set_window_title(&mut self, text: &str) {
+        self.push(Action::SetWindowTitle(text.to_owned()));
+    }
+
+    fn set_icon_name(&mut self, text: &str) {
+        self.push(Action::SetIconName(text.to_owned()));
+    }
+
+    fn push_title(&mut self, selection: TitleSelection) {
+        self.push(Action::PushTitle(selection));
+    }
+
+    fn pop_title(&mut self, selection: TitleSelection) {
+        self.push(Action::PopTitle(selection));
+    }
+
+    fn report_size(&mut self, report: SizeReport) {
+        self.push(Action::ReportSize(report));
+    }
+
+    fn window_operation(&mut self, operation: WindowOperation) {
+        self.push(Action::WindowOperation(operation));
+    }
+
+    fn report_device_attributes(&mut self) {
+        self.push(Action::ReportDeviceAttributes);
+    }
+
+    fn report_color_scheme(&mut self) {
+        self.push(Action::ReportColorScheme);
+    }
+
+    fn report_mode(&mut self, mode: u16) {
+        self.push(Action::ReportMode(mode));
+    }
+
+    fn graphics_attributes(&mut self, item: u16, action: u16, values: [u16; 2]) {
+        self.push(Action::GraphicsAttributes {
+            item,
+            action,
+            values,
+        });
+    }
+
+    fn sixel_image(&mut self, data: &[u8], transparent_background: bool) {
+        self.push(Action::SixelImage {
+            data: Bytes(data.to_vec()),
+            transparent_background,
+        });
+    }
+
+    fn kitty_graphics(&mut self, command: &[u8]) {
+        self.push(Action::KittyGraphics(Bytes(command.to_vec())));
+    }
+
+    fn inline_image(&mut self, file: &[u8]) {
+        self.push(Action::InlineImage(Bytes(file.to_vec())));
+    }
+
+    fn toggle_behaviour(&mut self, behaviour: Behaviour, toggle: Toggle) {
+        self.push(Action::ToggleBehaviour(behaviour, toggle));
+    }
+}
+
+#[test]
+fn collect_actions_with_offsets() {
+    let mut collector = ActionCollector::default();
+    let incomplete = collector.parse(b"ab\x1b[2Jc\xffd\x1b[");
+
+    let actions = collector
+        .actions
+        .iter()
+        .map(|(offset, action)| format!("{} {:?}", offset, action))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        actions,
+        [
+            "0 Text(\"ab\")",
+            "2 ClearScreen(All)",
+            "6 Text(\"c\")",
+            "7 InvalidUtf8(b\"\\xff\")",
+            "8 Text(\"d\")",
+        ]
+    );
+    assert_eq!(incomplete, b"\x1b[");
+}
+
+#[test]
+fn parse_control_strings_in_pieces() {
+    fn parse(pieces: &[&[u8]], limit: usize) -> Vec<String> {
+        let mut parser = super::control_code::Parser::default();
+        let mut collector = ActionCollector::default();
+        for piece in pieces {
+            parser.parse(piece, &mut collector, limit);
+        }
+
+        collector
+            .actions
+            .iter()
+            .map(|(_, action)| format!("{:?}", action))
+            .collect()
+    }
+
+    // The terminator may be split between reads
+    assert_eq!(
+        parse(&[b"a\x1b_Gi=1", b";AA\x1b", b"\\b"], 100),
+        ["Text(\"a\")", "KittyGraphics(b\"i=1;AA\")", "Text(\"b\")"]
+    );
+
+    // Strings are cancelled by CAN and by other escape sequences
+    assert_eq!(
+        parse(&[b"\x1bPq#0", b"!\x18a"], 100),
+        [
+            "InvalidControlSequence(b\"\\x1bPq#0!\\x18\")",
+            "Text(\"a\")"
+        ]
+    );
+    assert_eq!(
+        parse(&[b"\x1b]2;title\x1b", b"[2J"], 100),
+        [
+            "InvalidControlSequence(b\"\\x1b]2;title\")",
+            "ClearScreen(All)"
+        ]
+    );
+
+    // and dropped when they are too long
+    assert_eq!(
+        parse(&[b"\x1b_G", b"AAAA", b"AAAA\x1b\\b"], 4),
+        ["InvalidControlSequence(b\"\\x1b_GAAAA\")", "Text(\"b\")"]
+    );
+}
+
+#[test]
+fn parse_title_stack_selection() {
+    let mut collector = ActionCollector::default();
+    collector.parse(b"\x1b[22t\x1b[22;1t\x1b[23;2t\x1b[23;3t");
+
+    let actions = collector
+        .actions
+        .iter()
+        .map(|(_, action)| format!("{:?}", action))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        actions,
+        [
+            "PushTitle(Both)",
+            "PushTitle(IconName)",
+            "PopTitle(WindowTitle)",
+            "InvalidControlSequence(b\"\\x1b[23;3t\")",
+        ]
+    );
+}
+
+#[test]
+fn parse_titles_with_utf8() {
+    let mut collector = ActionCollector::default();
+    collector.parse("\x1b]2;héllo\x1b\\\x1b]1;日本\x07".as_bytes());
+    collector.parse(b"\x1b]0;a\xffb\x07");
+
+    let actions = collector
+        .actions
+        .iter()
+        .map(|(_, action)| format!("{:?}", action))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        actions,
+        [
+            "SetWindowTitle(\"héllo\")",
+            "SetIconName(\"日本\")",
+            "SetIconName(\"a\u{FFFD}b\")",
+            "SetWindowTitle(\"a\u{FFFD}b\")",
+        ]
+    );
+}
+
+#[test]
+fn ignore_unknown_charsets() {
+    let mut collector = ActionCollector::default();
+    let incomplete = collector.parse(b"\x1b(0\x1b(%5\x1b)<x\x1b(%");
+
+    let actions = collector
+        .actions
+        .iter()
+        .map(|(_, action)| format!("{:?}", action))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        actions,
+        ["SetCharset(G0, DecSpecialGraphics)", "Text(\"x\")"]
+    );
+    assert_eq!(incomplete, b"\x1b(%");
+}
@@ -12,6 +12,12 @@ pub trait Terminal {
     /// Makes an audible bell
     fn bell(&mut self);
 
+    /// Full reset to the initial state (`RIS`)
+    fn reset(&mut self);
+
+    /// Reset modes and attributes, but keep the contents of the screen (`DECSTR`)
+    fn soft_reset(&mut self);
+
     // === CURSOR === //
 
     /// Move cursor to next column that is an multiple of 8
@@ -58,6 +64,14 @@ pub trait Terminal {
     /// Set the color of the cursor to the default
     fn reset_cursor_color(&mut self);
 
+    // === CHARACTER SETS === //
+
+    /// Designate the character set used by one of the slots G0 or G1
+    fn set_charset(&mut self, slot: CharsetSlot, charset: Charset);
+
+    /// Select which slot is used to translate incoming text (`SI` and `SO`)
+    fn use_charset(&mut self, slot: CharsetSlot);
+
     // === SCROLLING === //
 
     /// Set the area within which content should scroll.
@@ -76,6 +90,14 @@ pub trait Terminal {
 
     fn erase(&mut self, count: u16);
 
+    /// Fill the screen with `E`s (`DECALN`)
+    fn screen_alignment_test(&mut self);
+
+    // === LINE SIZE === //
+
+    /// Set how the characters on the cursor's row are scaled
+    fn set_line_size(&mut self, size: crate::grid::LineSize);
+
     // === CHARACTER STYLE === //
 
     /// Set the style of characters
@@ -103,6 +125,51 @@ pub trait Terminal {
     /// Set the title of the window
     fn set_window_title(&mut self, text: &str);
 
+    /// Set the name shown for the window when it is minimized
+    fn set_icon_name(&mut self, text: &str);
+
+    /// Save the window title or icon name, or both, on the title stack
+    fn push_title(&mut self, selection: TitleSelection);
+
+    /// Restore the window title or icon name, or both, from the top of the title stack
+    fn pop_title(&mut self, selection: TitleSelection);
+
+    // === WINDOW === //
+
+    /// Report the size of the text area, or a cell, to the application
+    fn report_size(&mut self, report: SizeReport);
+
+    /// Request that the window is moved, resized, iconified, etc.
+    fn window_operation(&mut self, operation: WindowOperation);
+
+    // === REPORTS === //
+
+    /// Report which features are supported (`DA1`)
+    fn report_device_attributes(&mut self);
+
+    /// Report whether the user prefers a light or dark color scheme (`CSI ? 996 n`)
+    fn report_color_scheme(&mut self);
+
+    /// Report whether a private mode is set (`DECRQM`)
+    fn report_mode(&mut self, mode: u16);
+
+    /// Read or change the number of sixel color registers or the maximum size of sixel images
+    /// (`XTSMGRAPHICS`). `item` and `action` are passed through as given so that unknown values
+    /// can be reported back as errors.
+    fn graphics_attributes(&mut self, item: u16, action: u16, values: [u16; 2]);
+
+    // === GRAPHICS === //
+
+    /// Display an image encoded as sixels at the cursor. `data` is everything after the `q`.
+    fn sixel_image(&mut self, data: &[u8], transparent_background: bool);
+
+    /// Handle a command of the kitty graphics protocol. `command` is everything after the `G`.
+    fn kitty_graphics(&mut self, command: &[u8]);
+
+    /// Display an image sent with iTerm2's `OSC 1337 ; File=` sequence. `file` is everything
+    /// after `File=`.
+    fn inline_image(&mut self, file: &[u8]);
+
     // === BEHAVIOUR === //
 
     /// If enabled: arrow keys should send application codes instead of ANSI codes
@@ -132,6 +199,132 @@ pub enum ClearRegion {
     All,
 }
 
+/// Which names of the window are saved on, or restored from, the title stack (`CSI 22 t`,
+/// `CSI 23 t`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TitleSelection {
+    Both,
+    IconName,
+    WindowTitle,
+}
+
+impl TitleSelection {
+    pub fn includes_icon_name(self) -> bool {
+        matches!(self, TitleSelection::Both | TitleSelection::IconName)
+    }
+
+    pub fn includes_window_title(self) -> bool {
+        matches!(self, TitleSelection::Both | TitleSelection::WindowTitle)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SizeReport {
+    /// Size of the text area in pixels (`CSI 14 t`)
+    TextAreaPixels,
+    /// Size of a single cell in pixels (`CSI 16 t`)
+    CellPixels,
+    /// Size of the text area in characters (`CSI 18 t`)
+    TextAreaCells,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WindowOperation {
+    Deiconify,
+    Iconify,
+    /// Move the top-left corner of the window to the given pixel coordinates
+    Move {
+        x: u16,
+        y: u16,
+    },
+    /// Resize the text area to a size in pixels. Omitted dimensions are left unchanged.
+    ResizePixels {
+        height: Option<u16>,
+        width: Option<u16>,
+    },
+    Raise,
+    Lower,
+    /// Resize the text area to a size in characters. Omitted dimensions are left unchanged.
+    ResizeCells {
+        rows: Option<u16>,
+        cols: Option<u16>,
+    },
+    Maximize,
+    Unmaximize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CharsetSlot {
+    G0,
+    G1,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Charset {
+    /// US ASCII (`B`)
+    Ascii,
+    /// United Kingdom (`A`), where `#` is replaced by `£`
+    British,
+    /// DEC Special Character and Line Drawing Set (`0`)
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    fn from_designator(byte: u8) -> Option<Charset> {
+        match byte {
+            b'B' => Some(Charset::Ascii),
+            b'A' => Some(Charset::British),
+            b'0' => Some(Charset::DecSpecialGraphics),
+            _ => None,
+        }
+    }
+
+    /// Map a character into this character set.
+    pub fn translate(self, ch: char) -> char {
+        match self {
+            Charset::Ascii => ch,
+            Charset::British => match ch {
+                '#' => '£',
+                _ => ch,
+            },
+            Charset::DecSpecialGraphics => match ch {
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => ch,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct CursorStyle {
     pub shape: CursorShape,
@@ -201,11 +394,18 @@ macro_rules! enumeration {
 enumeration! {
     #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
     pub enum Behaviour: u16 {
-        ApplicationCursor = 1,
-        ShowCursor        = 25,
-        AlternateBuffer   = 47,
-        FocusEvents       = 1004,
-        BracketedPaste    = 2004,
+        ApplicationCursor  = 1,
+        ColumnMode         = 3,
+        ReverseVideo       = 5,
+        OriginMode         = 6,
+        ShowCursor         = 25,
+        AllowColumnMode    = 40,
+        AlternateBuffer    = 47,
+        FocusEvents        = 1004,
+        AlternateScroll    = 1007,
+        BracketedPaste     = 2004,
+        GraphemeClusters   = 2027,
+        ColorSchemeUpdates = 2031,
     }
 }
 
@@ -239,21 +439,154 @@ pub enum ParseError {
     Invalid,
 }
 
+/// Parses input which arrives in pieces, such as reads from the pseudoterminal, keeping sequences
+/// which are cut off until the rest arrives.
+///
+/// Control strings (`DCS`, `OSC` and `APC`) can be much longer than a single read, such as when
+/// they contain an image, so they are collected as they arrive instead of being parsed again from
+/// the start with every read. Strings longer than the limit are dropped.
+#[derive(Debug, Default)]
+pub struct Parser {
+    /// The start of a sequence which did not end before the input did.
+    residual: Vec<u8>,
+    /// Set while `residual` holds a control string which has not ended yet.
+    string: Option<PendingString>,
+}
+
+#[derive(Debug)]
+struct PendingString {
+    operating_system: bool,
+    /// The last input ended with the `ESC` which may start the String Terminator. It is not kept
+    /// in `residual` until the next byte is known.
+    escape: bool,
+    /// The string went over the limit, so only its start is kept in `residual` and the rest of it
+    /// is skipped.
+    dropped: bool,
+}
+
+/// Number of bytes kept of a control string which is dropped, to report it.
+const DROPPED_STRING_START: usize = 64;
+
+impl Parser {
+    pub fn parse(&mut self, mut input: &[u8], terminal: &mut impl Terminal, limit: usize) {
+        if let Some(mut string) = self.string.take() {
+            let length = if string.escape {
+                if input.first() == Some(&b'\\') {
+                    self.push_string_bytes(&string, b"\x1b");
+                    Some(1)
+                } else {
+                    // Another escape sequence cancels the string, and is parsed below
+                    Some(0)
+                }
+            } else {
+                util::string_length(input, string.operating_system)
+            };
+
+            match length {
+                Some(length) => {
+                    self.push_string_bytes(&string, &input[..length]);
+                    let residual = std::mem::take(&mut self.residual);
+                    // Strings which were cancelled are left over
+                    let invalid = if string.dropped {
+                        &residual
+                    } else {
+                        parse(&residual, terminal)
+                    };
+                    if !invalid.is_empty() {
+                        terminal.invalid_control_sequence(invalid);
+                    }
+
+                    if string.escape && length == 0 {
+                        self.residual.push(b'\x1b');
+                    }
+                    input = &input[length..];
+                }
+                None => {
+                    string.escape = input.last() == Some(&b'\x1b');
+                    let data = &input[..input.len() - usize::from(string.escape)];
+                    self.push_string_bytes(&string, data);
+                    self.limit_string(&mut string, limit);
+                    self.string = Some(string);
+                    return;
+                }
+            }
+        }
+
+        let combined;
+        let bytes = if self.residual.is_empty() {
+            input
+        } else {
+            let mut bytes = std::mem::take(&mut self.residual);
+            bytes.extend_from_slice(input);
+            combined = bytes;
+            &combined
+        };
+
+        let residual = parse(bytes, terminal);
+        self.residual.extend_from_slice(residual);
+
+        // Control strings which did not end are collected from here on
+        if let [b'\x1b', introducer @ (b'P' | b']' | b'_'), ..] = *self.residual {
+            let escape = self.residual.len() > 2 && self.residual.last() == Some(&b'\x1b');
+            if escape {
+                self.residual.pop();
+            }
+
+            let mut string = PendingString {
+                operating_system: introducer == b']',
+                escape,
+                dropped: false,
+            };
+            self.limit_string(&mut string, limit);
+            self.string = Some(string);
+        }
+    }
+
+    fn push_string_bytes(&mut self, string: &PendingString, bytes: &[u8]) {
+        if !string.dropped {
+            self.residual.extend_from_slice(bytes);
+        }
+    }
+
+    fn limit_string(&mut self, string: &mut PendingString, limit: usize) {
+        if self.residual.len() > limit {
+            string.dropped = true;
+            self.residual.truncate(DROPPED_STRING_START);
+            self.residual.shrink_to_fit();
+        }
+    }
+}
+
 pub fn parse<'a>(bytes: &'a [u8], terminal: &mut impl Terminal) -> &'a [u8] {
+    parse_with_offsets(bytes, terminal, |_, _| {})
+}
+
+/// Same as `parse`, but calls `at_offset` with the offset into `bytes` of every control sequence or
+/// run of text before it is passed to the terminal.
+pub fn parse_with_offsets<'a, T: Terminal>(
+    bytes: &'a [u8],
+    terminal: &mut T,
+    mut at_offset: impl FnMut(&mut T, usize),
+) -> &'a [u8] {
     let mut remaining = bytes;
     let mut assumed_text = bytes;
 
+    let offset = |slice: &[u8]| bytes.len() - slice.len();
+
     loop {
         match remaining {
-            [] => return emit_text(assumed_text, terminal),
+            [] => return emit_text(assumed_text, offset(assumed_text), terminal, &mut at_offset),
 
             [byte, ..] if byte.is_ascii_control() => {
                 let text = &assumed_text[..assumed_text.len() - remaining.len()];
-                let invalid = emit_text(text, terminal);
+                let invalid = emit_text(text, offset(assumed_text), terminal, &mut at_offset);
                 if !invalid.is_empty() {
+                    at_offset(terminal, offset(remaining) - invalid.len());
                     terminal.invalid_utf8(invalid);
                 }
 
+                at_offset(terminal, offset(remaining));
+
                 let mut iter = remaining.iter();
                 match parse_control_sequence(&mut iter, terminal) {
                     Ok(()) => {}
@@ -272,8 +605,15 @@ pub fn parse<'a>(bytes: &'a [u8], terminal: &mut impl Terminal) -> &'a [u8] {
     }
 }
 
-fn emit_text<'a>(mut bytes: &'a [u8], terminal: &mut impl Terminal) -> &'a [u8] {
+fn emit_text<'a, T: Terminal>(
+    mut bytes: &'a [u8],
+    mut offset: usize,
+    terminal: &mut T,
+    at_offset: &mut impl FnMut(&mut T, usize),
+) -> &'a [u8] {
     while !bytes.is_empty() {
+        at_offset(terminal, offset);
+
         match std::str::from_utf8(bytes) {
             Ok(text) => {
                 terminal.text(text);
@@ -284,15 +624,19 @@ fn emit_text<'a>(mut bytes: &'a [u8], terminal: &mut impl Terminal) -> &'a [u8]
 
                 // SAFETY: `bytes` is valid UTF-8 up until `error.valid_up_to()`
                 let text = unsafe { std::str::from_utf8_unchecked(valid) };
-                terminal.text(text);
+                if !text.is_empty() {
+                    terminal.text(text);
+                }
 
                 match error.error_len() {
                     // Reached end of bytes
                     None => return invalid,
                     Some(invalid_len) => {
                         let (invalid, rest) = invalid.split_at(invalid_len);
+                        at_offset(terminal, offset + valid.len());
                         terminal.invalid_utf8(&invalid);
                         bytes = rest;
+                        offset += valid.len() + invalid_len;
                     }
                 }
             }
@@ -311,6 +655,8 @@ fn parse_control_sequence(bytes: ByteIter, terminal: &mut impl Terminal) -> Pars
         b'\x09' => terminal.tab(),
         b'\r' => terminal.carriage_return(),
         b'\n' => terminal.line_feed(),
+        b'\x0e' => terminal.use_charset(CharsetSlot::G1),
+        b'\x0f' => terminal.use_charset(CharsetSlot::G0),
         b'\x1b' => parse_escape_sequence(bytes, terminal)?,
         _ => return Err(ParseError::Invalid),
     }
@@ -326,9 +672,21 @@ fn parse_escape_sequence(bytes: ByteIter, terminal: &mut impl Terminal) -> Parse
         // Operating System Command
         b']' => parse_operating_system_command(bytes, terminal)?,
 
-        b'(' => {
-            bytes.next().ok_or(ParseError::Incomplete)?;
-        }
+        // Device Control String
+        b'P' => parse_device_control_string(bytes, terminal)?,
+
+        // Application Program Command
+        b'_' => parse_application_program_command(bytes, terminal)?,
+
+        b'(' => parse_charset_designation(bytes, CharsetSlot::G0, terminal)?,
+        b')' => parse_charset_designation(bytes, CharsetSlot::G1, terminal)?,
+
+        b'c' => terminal.reset(),
+
+        b'#' => parse_line_attribute(bytes, terminal)?,
+
+        b'7' => terminal.save_cursor(),
+        b'8' => terminal.restore_cursor(),
 
         b'M' => terminal.reverse_line_feed(),
 
@@ -338,33 +696,97 @@ fn parse_escape_sequence(bytes: ByteIter, terminal: &mut impl Terminal) -> Parse
     Ok(())
 }
 
-fn parse_operating_system_command(
+fn parse_line_attribute(bytes: ByteIter, terminal: &mut impl Terminal) -> ParseResult<()> {
+    use crate::grid::LineSize;
+
+    match bytes.next().ok_or(ParseError::Incomplete)? {
+        b'3' => terminal.set_line_size(LineSize::DoubleHeightTop),
+        b'4' => terminal.set_line_size(LineSize::DoubleHeightBottom),
+        b'5' => terminal.set_line_size(LineSize::Single),
+        b'6' => terminal.set_line_size(LineSize::DoubleWidth),
+        b'8' => terminal.screen_alignment_test(),
+        _ => return Err(ParseError::Invalid),
+    }
+
+    Ok(())
+}
+
+fn parse_charset_designation(
+    bytes: ByteIter,
+    slot: CharsetSlot,
+    terminal: &mut impl Terminal,
+) -> ParseResult<()> {
+    // Some designators start with an intermediate byte, such as `%5` for DEC Supplemental
+    let intermediate = util::take_while_in_range(bytes, 0x20..=0x2f)?;
+    let designator = *bytes.next().ok_or(ParseError::Incomplete)?;
+
+    // Like xterm, other character sets are ignored, and the slot keeps its character set
+    if let (b"", Some(charset)) = (intermediate, Charset::from_designator(designator)) {
+        terminal.set_charset(slot, charset);
+    }
+
+    Ok(())
+}
+
+fn parse_device_control_string(bytes: ByteIter, terminal: &mut impl Terminal) -> ParseResult<()> {
+    let string = util::take_string(bytes, false)?;
+
+    let mut string = string.iter();
+    let (parameters, intermediate, terminator) =
+        parse_control_sequence_parts(&mut string).map_err(|_| ParseError::Invalid)?;
+    let data = string.as_slice();
+
+    match (intermediate, terminator) {
+        (b"", b'q') => {
+            let [_aspect_ratio, background, _grid_size] = Argument::multi(parameters)?;
+            terminal.sixel_image(data, background.with_default(0) == 1);
+        }
+        _ => return Err(ParseError::Invalid),
+    }
+
+    Ok(())
+}
+
+fn parse_application_program_command(
     bytes: ByteIter,
     terminal: &mut impl Terminal,
 ) -> ParseResult<()> {
-    let parameters = util::take_while_in_range(bytes, 0x20..=0x7e)?;
-    let terminator = bytes.next().ok_or(ParseError::Incomplete)?;
+    let data = util::take_string(bytes, false)?;
 
-    if !matches!(terminator, b'\x07' | b'\x03') {
-        return Err(ParseError::Invalid);
+    match data {
+        [b'G', command @ ..] => terminal.kitty_graphics(command),
+        _ => return Err(ParseError::Invalid),
     }
 
+    Ok(())
+}
+
+fn parse_operating_system_command(
+    bytes: ByteIter,
+    terminal: &mut impl Terminal,
+) -> ParseResult<()> {
+    let parameters = util::take_string(bytes, true)?;
+
     let mut arguments = ArgumentList::new(parameters);
 
     match arguments.next()?.with_default(0) {
-        // Change "icon name" and window title. The former does not apply.
+        // Change icon name and window title.
         0 => {
-            let text = std::str::from_utf8(arguments.next_slice()).unwrap();
-            terminal.set_window_title(text);
+            let text = String::from_utf8_lossy(arguments.next_slice());
+            terminal.set_icon_name(&text);
+            terminal.set_window_title(&text);
         }
 
-        // Change "icon name" (does not apply)
-        1 => {}
+        // Change icon name.
+        1 => {
+            let text = String::from_utf8_lossy(arguments.next_slice());
+            terminal.set_icon_name(&text);
+        }
 
         // Change window title.
         2 => {
-            let text = std::str::from_utf8(arguments.next_slice()).unwrap();
-            terminal.set_window_title(text);
+            let text = String::from_utf8_lossy(arguments.next_slice());
+            terminal.set_window_title(&text);
         }
 
         // Set X-property on top-level window (does not apply)
@@ -372,6 +794,12 @@ fn parse_operating_system_command(
 
         112 => terminal.reset_cursor_color(),
 
+        // iTerm2 proprietary sequences
+        1337 => match arguments.rest() {
+            [b'F', b'i', b'l', b'e', b'=', file @ ..] => terminal.inline_image(file),
+            _ => return Err(ParseError::Invalid),
+        },
+
         _ => return Err(ParseError::Invalid),
     }
 
@@ -385,9 +813,17 @@ fn parse_escape_control_sequence(bytes: ByteIter, terminal: &mut impl Terminal)
         ([b'?', arguments @ ..], b"") => {
             parse_escape_question_terminator(arguments, terminator, terminal)
         }
+        ([b'?', arguments @ ..], b"$") if terminator == b'p' => {
+            terminal.report_mode(Argument::single(arguments)?.with_default(0));
+            Ok(())
+        }
 
         (arguments, b"") => parse_escape_standard_terminator(arguments, terminator, terminal),
         (arguments, b" ") => parse_escape_space_terminator(arguments, terminator, terminal),
+        (b"", b"!") if terminator == b'p' => {
+            terminal.soft_reset();
+            Ok(())
+        }
 
         _ => Err(ParseError::Invalid),
     }
@@ -415,6 +851,22 @@ fn parse_escape_question_terminator(
     match terminator {
         b'h' => parse_question_terminator_toggle(parameters, Toggle::Enabled, terminal),
         b'l' => parse_question_terminator_toggle(parameters, Toggle::Disabled, terminal),
+        b'S' => {
+            let [item, action, first, second] = Argument::multi(parameters)?;
+            terminal.graphics_attributes(
+                item.with_default(0),
+                action.with_default(0),
+                [first.with_default(0), second.with_default(0)],
+            );
+            Ok(())
+        }
+        b'n' => match Argument::single(parameters)?.with_default(0) {
+            996 => {
+                terminal.report_color_scheme();
+                Ok(())
+            }
+            _ => Err(ParseError::Invalid),
+        },
         _ => Err(ParseError::Invalid),
     }
 }
@@ -498,6 +950,13 @@ fn parse_escape_standard_terminator(
 
         b'X' => terminal.erase(Argument::single(parameters)?.with_default(1)),
 
+        b't' => parse_window_manipulation(parameters, terminal)?,
+
+        b'c' => match Argument::single(parameters)?.with_default(0) {
+            0 => terminal.report_device_attributes(),
+            _ => return Err(ParseError::Invalid),
+        },
+
         b'r' => {
             let [top, bottom] = Argument::multi(parameters)?;
             let top = top.with_default(1) - 1;
@@ -511,6 +970,61 @@ fn parse_escape_standard_terminator(
     Ok(())
 }
 
+fn parse_window_manipulation(parameters: &[u8], terminal: &mut impl Terminal) -> ParseResult<()> {
+    let mut arguments = ArgumentList::new(parameters);
+
+    let operation = arguments.next()?.with_default(0);
+    match operation {
+        1 => terminal.window_operation(WindowOperation::Deiconify),
+        2 => terminal.window_operation(WindowOperation::Iconify),
+        3 => {
+            let x = arguments.next()?.with_default(0);
+            let y = arguments.next()?.with_default(0);
+            terminal.window_operation(WindowOperation::Move { x, y })
+        }
+        4 => {
+            let height = arguments.next()?.to_option();
+            let width = arguments.next()?.to_option();
+            terminal.window_operation(WindowOperation::ResizePixels { height, width })
+        }
+        5 => terminal.window_operation(WindowOperation::Raise),
+        6 => terminal.window_operation(WindowOperation::Lower),
+        8 => {
+            let rows = arguments.next()?.to_option();
+            let cols = arguments.next()?.to_option();
+            terminal.window_operation(WindowOperation::ResizeCells { rows, cols })
+        }
+        9 => match arguments.next()?.with_default(0) {
+            0 => terminal.window_operation(WindowOperation::Unmaximize),
+            1 => terminal.window_operation(WindowOperation::Maximize),
+            _ => return Err(ParseError::Invalid),
+        },
+
+        14 => terminal.report_size(SizeReport::TextAreaPixels),
+        16 => terminal.report_size(SizeReport::CellPixels),
+        18 => terminal.report_size(SizeReport::TextAreaCells),
+
+        22 | 23 => {
+            let selection = match arguments.next()?.with_default(0) {
+                0 => TitleSelection::Both,
+                1 => TitleSelection::IconName,
+                2 => TitleSelection::WindowTitle,
+                _ => return Err(ParseError::Invalid),
+            };
+
+            if operation == 22 {
+                terminal.push_title(selection)
+            } else {
+                terminal.pop_title(selection)
+            }
+        }
+
+        _ => return Err(ParseError::Invalid),
+    }
+
+    Ok(())
+}
+
 fn parse_escape_space_terminator(
     params: &[u8],
     terminator: u8,
@@ -639,6 +1153,51 @@ mod util {
         Ok(matching)
     }
 
+    /// Take a control string up until its terminator, and skip past the terminator. Strings which
+    /// are cancelled are invalid, see `string_length`.
+    pub fn take_string<'a>(
+        bytes: ByteIter<'_, 'a>,
+        operating_system: bool,
+    ) -> ParseResult<&'a [u8]> {
+        let slice = bytes.as_slice();
+        let length = string_length(slice, operating_system).ok_or(ParseError::Incomplete)?;
+
+        let (string, rest) = slice.split_at(length);
+        *bytes = rest.iter();
+
+        match string {
+            [data @ .., b'\x1b', b'\\'] => Ok(data),
+            [data @ .., b'\x07' | b'\x03'] if operating_system => Ok(data),
+            _ => Err(ParseError::Invalid),
+        }
+    }
+
+    /// Length of the control string at the start of `bytes`, including the bytes which end it, or
+    /// `None` if it continues past the end of `bytes`.
+    ///
+    /// Strings end with a String Terminator (`ESC \`). Like on a VT, they are cancelled by `CAN`
+    /// or `SUB`, and by any other escape sequence, which is not part of the string. Operating
+    /// System Commands also end with `BEL` (or `ETX`), and with any other C0 control character.
+    /// Bytes from `0x80` on are part of the string, since they encode UTF-8 text.
+    pub fn string_length(bytes: &[u8], operating_system: bool) -> Option<usize> {
+        for (index, &byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\x1b' => {
+                    return match bytes.get(index + 1) {
+                        Some(b'\\') => Some(index + 2),
+                        Some(_) => Some(index),
+                        None => None,
+                    }
+                }
+                b'\x18' | b'\x1a' => return Some(index + 1),
+                0x00..=0x1f if operating_system => return Some(index + 1),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
     pub fn take_while_in_range<'a, R>(bytes: ByteIter<'_, 'a>, range: R) -> ParseResult<&'a [u8]>
     where
         R: std::ops::RangeBounds<u8>,
@@ -759,6 +1318,11 @@ impl<'a> ArgumentList<'a> {
         }
     }
 
+    /// Everything that has not been parsed yet, including separators.
+    pub fn rest(&self) -> &'a [u8] {
+        self.parameters
+    }
+
     pub fn is_empty(&self) -> bool {
         self.parameters.is_empty()
     }
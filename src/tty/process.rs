@@ -0,0 +1,87 @@
+//! Information about the process in the foreground of the terminal, such as the program the user
+//! is running and its working directory.
+
+use nix::libc;
+
+/// Flavor of `proc_pidinfo` which returns the current and root directories of a process.
+const PROC_PIDVNODEPATHINFO: libc::c_int = 9;
+
+/// Size of `struct vnode_info`, which comes before the path in `struct vnode_info_path`.
+const VNODE_INFO_SIZE: usize = 152;
+const MAXPATHLEN: usize = 1024;
+
+/// `struct vnode_info_path`, only reading the path.
+#[repr(C)]
+struct VnodeInfoPath {
+    _info: [u8; VNODE_INFO_SIZE],
+    path: [libc::c_char; MAXPATHLEN],
+}
+
+/// `struct proc_vnodepathinfo`.
+#[repr(C)]
+struct ProcVnodePathInfo {
+    current_dir: VnodeInfoPath,
+    _root_dir: VnodeInfoPath,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Process {
+    /// Name of the program, such as `ssh`.
+    pub name: String,
+    /// Current working directory, if it could be read.
+    pub cwd: Option<std::path::PathBuf>,
+}
+
+impl Process {
+    pub fn from_pid(pid: nix::unistd::Pid) -> Option<Process> {
+        let name = process_name(pid)?;
+        let cwd = current_dir(pid);
+        Some(Process { name, cwd })
+    }
+}
+
+fn process_name(pid: nix::unistd::Pid) -> Option<String> {
+    let mut buffer = [0u8; 2 * MAXPATHLEN];
+
+    let length = unsafe {
+        libc::proc_name(
+            pid.as_raw(),
+            buffer.as_mut_ptr().cast(),
+            buffer.len() as u32,
+        )
+    };
+    if length <= 0 {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&buffer[..length as usize]).into_owned())
+}
+
+fn current_dir(pid: nix::unistd::Pid) -> Option<std::path::PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut info = std::mem::MaybeUninit::<ProcVnodePathInfo>::zeroed();
+    let size = std::mem::size_of::<ProcVnodePathInfo>() as libc::c_int;
+
+    let written = unsafe {
+        libc::proc_pidinfo(
+            pid.as_raw(),
+            PROC_PIDVNODEPATHINFO,
+            0,
+            info.as_mut_ptr().cast(),
+            size,
+        )
+    };
+    if written != size {
+        return None;
+    }
+
+    // SAFETY: the buffer was zeroed, and filled in completely by the kernel
+    let info = unsafe { info.assume_init() };
+    let path = unsafe { std::ffi::CStr::from_ptr(info.current_dir.path.as_ptr()) };
+    if path.to_bytes().is_empty() {
+        return None;
+    }
+
+    Some(std::ffi::OsStr::from_bytes(path.to_bytes()).into())
+}
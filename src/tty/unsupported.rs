@@ -0,0 +1,228 @@
+//! A tally of the control sequences an application sent which the terminal does not support.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Sequences are cut to this many bytes, so long payloads don't use much memory.
+const MAX_SEQUENCE_LENGTH: usize = 256;
+
+/// Number of distinct sequences that are remembered. Later ones are only counted.
+const MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// The parser did not recognise the sequence.
+    Unrecognised,
+    /// The sequence was recognised, but is not implemented.
+    Unimplemented,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub kind: Kind,
+    /// Raw bytes of the sequence, cut to `MAX_SEQUENCE_LENGTH`.
+    pub bytes: Vec<u8>,
+    /// Human readable form, such as `CSI ? 1004 h`.
+    pub decoded: String,
+    pub count: u64,
+    /// Time since the start of the session when the sequence was first seen.
+    pub first_seen: Duration,
+}
+
+pub struct UnsupportedSequences {
+    started: Instant,
+    started_at: SystemTime,
+    /// In the order they were first seen.
+    entries: Vec<Entry>,
+    /// Index of the entry for each sequence.
+    index: HashMap<Vec<u8>, usize>,
+    /// Number of sequences not remembered because there were too many distinct ones.
+    dropped: u64,
+}
+
+impl Default for UnsupportedSequences {
+    fn default() -> Self {
+        UnsupportedSequences {
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            entries: Vec::new(),
+            index: HashMap::new(),
+            dropped: 0,
+        }
+    }
+}
+
+impl UnsupportedSequences {
+    pub fn record_unrecognised(&mut self, bytes: &[u8]) {
+        self.record(Kind::Unrecognised, bytes, None);
+    }
+
+    /// Record a sequence that is recognised as `name`, but not implemented.
+    pub fn record_unimplemented(&mut self, bytes: &[u8], name: &str) {
+        self.record(Kind::Unimplemented, bytes, Some(name));
+    }
+
+    fn record(&mut self, kind: Kind, bytes: &[u8], name: Option<&str>) {
+        let truncated = bytes.len() > MAX_SEQUENCE_LENGTH;
+        let bytes = &bytes[..bytes.len().min(MAX_SEQUENCE_LENGTH)];
+
+        if let Some(&index) = self.index.get(bytes) {
+            self.entries[index].count += 1;
+            return;
+        }
+
+        if self.entries.len() >= MAX_ENTRIES {
+            self.dropped += 1;
+            return;
+        }
+
+        let mut decoded = decode(bytes);
+        if truncated {
+            decoded.push_str(" …");
+        }
+        if let Some(name) = name {
+            decoded = format!("{} ({})", decoded, name);
+        }
+
+        self.index.insert(bytes.to_vec(), self.entries.len());
+        self.entries.push(Entry {
+            kind,
+            bytes: bytes.to_vec(),
+            decoded,
+            count: 1,
+            first_seen: self.started.elapsed(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.dropped == 0
+    }
+
+    /// One line per entry, followed by a line with the number of sequences that were dropped.
+    pub fn report_lines(&self) -> Vec<String> {
+        let mut lines = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let kind = match entry.kind {
+                    Kind::Unrecognised => "unrecognised",
+                    Kind::Unimplemented => "unimplemented",
+                };
+                format!(
+                    "{:>6}x  +{:>9.3}s  {:<13}  {}  {:?}",
+                    entry.count,
+                    entry.first_seen.as_secs_f64(),
+                    kind,
+                    entry.decoded,
+                    super::action::Bytes(entry.bytes.clone()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        if self.dropped != 0 {
+            lines.push(format!(
+                "{:>6}x  (too many distinct sequences)",
+                self.dropped
+            ));
+        }
+
+        lines
+    }
+
+    pub fn write_report(&self, output: &mut impl std::io::Write) -> std::io::Result<()> {
+        let started = self
+            .started_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        writeln!(
+            output,
+            "# unsupported sequences in the session started at {} (seconds since the Unix epoch)",
+            started.as_secs()
+        )?;
+        writeln!(
+            output,
+            "#  count  first seen  kind           sequence  bytes"
+        )?;
+
+        for line in self.report_lines() {
+            writeln!(output, "{}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Spell out a control sequence: control characters are named, numbers are kept together and
+/// everything else is separated by spaces.
+pub fn decode(bytes: &[u8]) -> String {
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let byte = bytes[index];
+        let next = bytes.get(index + 1).copied();
+
+        let run = |matches: fn(&u8) -> bool| {
+            bytes[index..]
+                .iter()
+                .take_while(|byte| matches(byte))
+                .count()
+        };
+
+        let (token, length) = match (byte, next) {
+            (0x1b, Some(b'[')) => ("CSI".to_owned(), 2),
+            (0x1b, Some(b']')) => ("OSC".to_owned(), 2),
+            (0x1b, Some(b'P')) => ("DCS".to_owned(), 2),
+            (0x1b, Some(b'_')) => ("APC".to_owned(), 2),
+            (0x1b, Some(b'\\')) => ("ST".to_owned(), 2),
+            (0x00..=0x1f, _) => (C0_NAMES[usize::from(byte)].to_owned(), 1),
+            (0x7f, _) => ("DEL".to_owned(), 1),
+            (b' ', _) => ("SP".to_owned(), 1),
+            (b'0'..=b'9', _) => {
+                let length = run(u8::is_ascii_digit);
+                (
+                    String::from_utf8_lossy(&bytes[index..index + length]).into_owned(),
+                    length,
+                )
+            }
+            (b'a'..=b'z' | b'A'..=b'Z', _) => {
+                let length = run(u8::is_ascii_alphabetic);
+                (
+                    String::from_utf8_lossy(&bytes[index..index + length]).into_owned(),
+                    length,
+                )
+            }
+            (0x21..=0x7e, _) => (char::from(byte).to_string(), 1),
+            _ => (format!("0x{:02X}", byte), 1),
+        };
+
+        tokens.push(token);
+        index += length;
+    }
+
+    tokens.join(" ")
+}
+
+const C0_NAMES: [&str; 32] = [
+    "NUL", "SOH", "STX", "ETX", "EOT", "ENQ", "ACK", "BEL", "BS", "HT", "LF", "VT", "FF", "CR",
+    "SO", "SI", "DLE", "DC1", "DC2", "DC3", "DC4", "NAK", "SYN", "ETB", "CAN", "EM", "SUB", "ESC",
+    "FS", "GS", "RS", "US",
+];
+
+#[test]
+fn tally_unsupported_sequences() {
+    let mut unsupported = UnsupportedSequences::default();
+    unsupported.record_unrecognised(b"\x1b[?9999h");
+    unsupported.record_unimplemented(b"\x1b[?1004h", "FocusEvents");
+    unsupported.record_unrecognised(b"\x1b[?9999h");
+
+    let entries = &unsupported.entries;
+    assert_eq!(entries.len(), 2);
+
+    assert_eq!(entries[0].decoded, "CSI ? 9999 h");
+    assert_eq!(entries[0].count, 2);
+    assert_eq!(entries[1].kind, Kind::Unimplemented);
+    assert_eq!(entries[1].decoded, "CSI ? 1004 h (FocusEvents)");
+
+    assert_eq!(decode(b"\x1b]2;a b\x07"), "OSC 2 ; a SP b BEL");
+}
@@ -0,0 +1,89 @@
+//! Which rows are shown: the bottom of the grid, or some way up into the history.
+
+/// How far to scroll. Positive amounts scroll up, towards earlier lines.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Scroll {
+    Lines(isize),
+    HalfPages(isize),
+    Pages(isize),
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Default)]
+pub struct Viewport {
+    /// Number of history lines shown above the grid. The view is at the bottom when it is zero.
+    offset: usize,
+    /// Lines pushed to the history as of the last call to `follow_history`.
+    pushed: u64,
+}
+
+impl Viewport {
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Move the view. It never goes past the oldest line of the history or below the grid.
+    pub fn scroll(&mut self, scroll: Scroll, rows: u16, history_len: usize) {
+        let page = usize::from(rows).max(1);
+
+        let lines = match scroll {
+            Scroll::Lines(lines) => lines,
+            Scroll::HalfPages(pages) => pages * (page / 2).max(1) as isize,
+            Scroll::Pages(pages) => pages * page as isize,
+            Scroll::Top => {
+                self.offset = history_len;
+                return;
+            }
+            Scroll::Bottom => {
+                self.offset = 0;
+                return;
+            }
+        };
+
+        self.offset = if lines >= 0 {
+            self.offset.saturating_add(lines as usize).min(history_len)
+        } else {
+            self.offset.saturating_sub(lines.unsigned_abs())
+        };
+    }
+
+    /// Keep the same lines in view while the history grows, unless the view is at the bottom.
+    pub fn follow_history(&mut self, pushed: u64, history_len: usize) {
+        let new_lines = pushed.saturating_sub(self.pushed);
+        self.pushed = pushed;
+
+        if self.offset != 0 {
+            let new_lines = new_lines.min(usize::MAX as u64) as usize;
+            self.offset = self.offset.saturating_add(new_lines);
+        }
+
+        // The history may also have been cleared
+        self.offset = self.offset.min(history_len);
+    }
+}
+
+#[test]
+fn scroll_and_follow_history() {
+    let mut viewport = Viewport::default();
+    viewport.scroll(Scroll::Pages(1), 10, 25);
+    viewport.scroll(Scroll::HalfPages(1), 10, 25);
+    assert_eq!(viewport.offset(), 15);
+
+    viewport.scroll(Scroll::Lines(20), 10, 25);
+    assert_eq!(viewport.offset(), 25);
+    viewport.scroll(Scroll::Lines(-30), 10, 25);
+    assert_eq!(viewport.offset(), 0);
+
+    // Output doesn't move the view at the bottom
+    viewport.follow_history(5, 30);
+    assert_eq!(viewport.offset(), 0);
+
+    viewport.scroll(Scroll::Lines(3), 10, 30);
+    viewport.follow_history(7, 32);
+    assert_eq!(viewport.offset(), 5);
+
+    viewport.scroll(Scroll::Top, 10, 32);
+    viewport.follow_history(7, 0);
+    assert_eq!(viewport.offset(), 0);
+}
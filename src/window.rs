@@ -3,7 +3,7 @@ pub mod cocoa;
 #[cfg(target_os = "macos")]
 pub use self::cocoa as platform;
 
-pub use platform::{EventLoop, EventLoopWaker, Window};
+pub use platform::{EventLoop, EventLoopWaker, SystemColorScheme, Window};
 
 #[derive(Debug)]
 pub struct WindowConfig {
@@ -16,7 +16,10 @@ pub enum Event {
     Inactive,
     Resize(PhysicalSize),
     KeyPress(Key, Modifiers),
+    Scroll(ScrollDelta),
     ScaleFactorChanged,
+    /// The light or dark appearance of the system changed.
+    ColorSchemeChanged,
     EventsCleared,
 }
 
@@ -32,6 +35,19 @@ pub enum Key {
     ArrowRight,
     ArrowUp,
     ArrowDown,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+}
+
+/// Distance scrolled by a mouse wheel or trackpad. Positive values scroll up, towards earlier
+/// content.
+#[derive(Debug, Copy, Clone)]
+pub enum ScrollDelta {
+    Lines(f32),
+    /// Trackpads scroll smoothly, in physical pixels.
+    Pixels(f32),
 }
 
 bitflags::bitflags! {
@@ -102,6 +102,10 @@ impl Window {
             sel!(keyDown:),
             key_down as extern "C" fn(&Object, Sel, CocoaId),
         );
+        window.add_method(
+            sel!(scrollWheel:),
+            scroll_wheel as extern "C" fn(&Object, Sel, CocoaId),
+        );
 
         window.register()
     }
@@ -131,6 +135,101 @@ impl Window {
         }
     }
 
+    /// Set the name shown for the window while it is minimized.
+    pub fn set_icon_name(&self, name: &str) {
+        use cocoa::base::nil;
+        use cocoa::foundation::NSString;
+
+        unsafe {
+            let new_name = NSString::alloc(nil).init_str(name);
+            let _: () = msg_send![self.raw, setMiniwindowTitle: new_name];
+        }
+    }
+
+    /// Resize the content area of the window to the given size in physical pixels.
+    pub fn set_inner_size(&self, size: super::PhysicalSize) {
+        use cocoa::appkit::NSWindow;
+        use cocoa::foundation::NSSize;
+
+        unsafe {
+            let scale = self.scale_factor();
+            let size = NSSize::new(size.width as f64 / scale, size.height as f64 / scale);
+            NSWindow::setContentSize_(self.raw, size);
+        }
+    }
+
+    /// Move the top-left corner of the window to the given physical pixel coordinates, relative
+    /// to the top-left corner of the screen.
+    pub fn set_position(&self, x: u32, y: u32) {
+        use cocoa::appkit::{NSScreen, NSWindow};
+        use cocoa::base::nil;
+        use cocoa::foundation::NSPoint;
+
+        unsafe {
+            let screen = NSWindow::screen(self.raw);
+            if screen == nil {
+                return;
+            }
+
+            let frame = NSScreen::frame(screen);
+            let scale = self.scale_factor();
+            let left = frame.origin.x + x as f64 / scale;
+            let top = frame.origin.y + frame.size.height - y as f64 / scale;
+            NSWindow::setFrameTopLeftPoint_(self.raw, NSPoint::new(left, top));
+        }
+    }
+
+    pub fn set_minimized(&self, minimized: bool) {
+        use cocoa::appkit::NSWindow;
+        use cocoa::base::nil;
+
+        unsafe {
+            if minimized {
+                NSWindow::miniaturize_(self.raw, nil);
+            } else {
+                NSWindow::deminiaturize_(self.raw, nil);
+            }
+        }
+    }
+
+    pub fn set_maximized(&self, maximized: bool) {
+        use cocoa::appkit::NSWindow;
+        use cocoa::base::{nil, BOOL, YES};
+
+        unsafe {
+            let zoomed: BOOL = msg_send![self.raw, isZoomed];
+            if (zoomed == YES) != maximized {
+                NSWindow::zoom_(self.raw, nil);
+            }
+        }
+    }
+
+    /// Bounce the icon in the dock once, if the application is not active.
+    pub fn request_attention(&self) {
+        use cocoa::foundation::NSInteger;
+
+        const NS_INFORMATIONAL_REQUEST: NSInteger = 10;
+
+        unsafe {
+            let app = cocoa::appkit::NSApp();
+            let _: NSInteger = msg_send![app, requestUserAttention: NS_INFORMATIONAL_REQUEST];
+        }
+    }
+
+    pub fn raise(&self) {
+        use cocoa::appkit::NSWindow;
+        use cocoa::base::nil;
+
+        unsafe { NSWindow::orderFront_(self.raw, nil) };
+    }
+
+    pub fn lower(&self) {
+        use cocoa::appkit::NSWindow;
+        use cocoa::base::nil;
+
+        unsafe { NSWindow::orderBack_(self.raw, nil) };
+    }
+
     pub fn content_view(&self) -> CocoaId {
         self.view
     }
@@ -170,6 +269,19 @@ impl Window {
             }
         }
     }
+
+    pub fn set_clipboard(&self, text: &str) {
+        use cocoa::appkit::NSPasteboard;
+        use cocoa::base::nil;
+        use cocoa::foundation::NSString;
+
+        unsafe {
+            let pasteboard = NSPasteboard::generalPasteboard(nil);
+            let string = NSString::alloc(nil).init_str(text);
+            pasteboard.clearContents();
+            pasteboard.setString_forType(string, cocoa::appkit::NSPasteboardTypeString);
+        }
+    }
 }
 
 impl EventLoop {
@@ -226,9 +338,12 @@ impl EventLoop {
                 (applicationWillTerminate:) => application_will_terminate as extern fn(&mut Object, Sel, CocoaId),
 
                 (applicationDidBecomeActive:) => application_did_become_active as extern fn(this: &Object, _cmd: Sel, _notification: id),
-                (applicationDidResignActive:) => application_did_resign_active as extern fn(this: &Object, _cmd: Sel, _notification: id)
+                (applicationDidResignActive:) => application_did_resign_active as extern fn(this: &Object, _cmd: Sel, _notification: id),
+
+                (observeValueForKeyPath:ofObject:change:context:) => observe_value as extern fn(&Object, Sel, id, id, id, *mut c_void)
             });
             self.app.setDelegate_(app_delegate);
+            Self::observe_appearance(self.app, app_delegate);
 
             let run_loop = core_foundation::runloop::CFRunLoop::get_main();
             Self::add_observers(&run_loop);
@@ -239,6 +354,27 @@ impl EventLoop {
         std::process::exit(0);
     }
 
+    /// Send `ColorSchemeChanged` whenever the appearance of the application changes, which follows
+    /// the system settings.
+    unsafe fn observe_appearance(app: CocoaId, observer: CocoaId) {
+        use cocoa::base::nil;
+        use cocoa::foundation::{NSString, NSUInteger};
+
+        // Appearances were added in macOS 10.14
+        let responds: cocoa::base::BOOL =
+            msg_send![app, respondsToSelector: sel!(effectiveAppearance)];
+        if responds != cocoa::base::YES {
+            return;
+        }
+
+        let key_path = NSString::alloc(nil).init_str("effectiveAppearance");
+        let options: NSUInteger = 0;
+        let _: () = msg_send![app, addObserver: observer
+                                  forKeyPath: key_path
+                                     options: options
+                                     context: std::ptr::null_mut::<c_void>()];
+    }
+
     fn add_observers(run_loop: &core_foundation::runloop::CFRunLoop) {
         use core_foundation::base::TCFType;
 
@@ -267,6 +403,44 @@ impl EventLoop {
     }
 }
 
+/// The light or dark appearance chosen in the system settings.
+pub struct SystemColorScheme;
+
+impl crate::color_scheme::SystemPreference for SystemColorScheme {
+    fn color_scheme(&self) -> Option<crate::color_scheme::ColorScheme> {
+        use crate::color_scheme::ColorScheme;
+        use cocoa::base::nil;
+        use cocoa::foundation::NSString;
+
+        unsafe {
+            let app = cocoa::appkit::NSApp();
+
+            // Appearances were added in macOS 10.14
+            let responds: cocoa::base::BOOL =
+                msg_send![app, respondsToSelector: sel!(effectiveAppearance)];
+            if responds != cocoa::base::YES {
+                return None;
+            }
+
+            let appearance: CocoaId = msg_send![app, effectiveAppearance];
+            let name: CocoaId = msg_send![appearance, name];
+            if name == nil {
+                return None;
+            }
+
+            let text = name.UTF8String();
+            let bytes = std::slice::from_raw_parts(text as *const u8, name.len());
+            let name = std::str::from_utf8_unchecked(bytes);
+
+            if name.contains("Dark") {
+                Some(ColorScheme::Dark)
+            } else {
+                Some(ColorScheme::Light)
+            }
+        }
+    }
+}
+
 impl EventLoop {
     pub fn create_waker(&self) -> EventLoopWaker {
         EventLoopWaker
@@ -310,6 +484,18 @@ extern "C" fn application_did_resign_active(_this: &Object, _cmd: Sel, _notifica
     HANDLER.send(super::Event::Inactive);
 }
 
+/// Key-value observer of `effectiveAppearance`, the only key path that is observed.
+extern "C" fn observe_value(
+    _this: &Object,
+    _cmd: Sel,
+    _key_path: CocoaId,
+    _object: CocoaId,
+    _change: CocoaId,
+    _context: *mut c_void,
+) {
+    HANDLER.send(super::Event::ColorSchemeChanged);
+}
+
 impl From<super::PhysicalSize> for cocoa::foundation::NSSize {
     fn from(size: super::PhysicalSize) -> Self {
         cocoa::foundation::NSSize::new(size.width as f64, size.height as f64)
@@ -338,6 +524,11 @@ extern "C" fn key_down(_this: &Object, _cmd: Sel, event: CocoaId) {
             0x7d => HANDLER.send(KeyPress(Key::ArrowDown, modifiers)),
             0x7e => HANDLER.send(KeyPress(Key::ArrowUp, modifiers)),
 
+            0x74 => HANDLER.send(KeyPress(Key::PageUp, modifiers)),
+            0x79 => HANDLER.send(KeyPress(Key::PageDown, modifiers)),
+            0x73 => HANDLER.send(KeyPress(Key::Home, modifiers)),
+            0x77 => HANDLER.send(KeyPress(Key::End, modifiers)),
+
             _ => {
                 let chars = event.charactersIgnoringModifiers();
 
@@ -362,6 +553,24 @@ extern "C" fn key_down(_this: &Object, _cmd: Sel, event: CocoaId) {
     }
 }
 
+extern "C" fn scroll_wheel(this: &Object, _cmd: Sel, event: CocoaId) {
+    use cocoa::appkit::{NSEvent, NSWindow};
+
+    unsafe {
+        let delta = NSEvent::scrollingDeltaY(event);
+
+        let delta = if NSEvent::hasPreciseScrollingDeltas(event) == cocoa::base::YES {
+            let window = this as *const Object as CocoaId;
+            let scale_factor = NSWindow::backingScaleFactor(window);
+            super::ScrollDelta::Pixels((delta * scale_factor) as f32)
+        } else {
+            super::ScrollDelta::Lines(delta as f32)
+        };
+
+        HANDLER.send(super::Event::Scroll(delta));
+    }
+}
+
 unsafe fn get_event_modifiers(event: CocoaId) -> super::Modifiers {
     use cocoa::appkit::{NSEvent, NSEventModifierFlags};
 